@@ -0,0 +1,116 @@
+use crate::calculations::map_to_u32;
+
+pub const ATTRIBUTES: usize = 6;
+
+/// Optional point values from the source, saved as extra properties.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attribute {
+	Intensity,
+	ReturnNumber,
+	NumberOfReturns,
+	Classification,
+	Color,
+	Time,
+}
+
+impl Attribute {
+	pub fn index(self) -> usize {
+		self as usize
+	}
+
+	/// File and display name for the project property.
+	pub fn property(self) -> (&'static str, &'static str) {
+		match self {
+			Self::Intensity => ("intensity", "Intensity"),
+			Self::ReturnNumber => ("return_number", "Return Number"),
+			Self::NumberOfReturns => ("number_of_returns", "Number of Returns"),
+			Self::Classification => ("classification", "Classification"),
+			Self::Color => (project::COLOR_PROPERTY, "Color"),
+			Self::Time => ("time", "GPS Time"),
+		}
+	}
+}
+
+/// Raw attribute values of a single point.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Attributes {
+	pub time: f64,
+	pub intensity: u16,
+	pub color: [u16; 3],
+	pub return_number: u8,
+	pub number_of_returns: u8,
+	pub classification: u8,
+	/// Tree label selected with `--segment-from`, `0` for points without a tree
+	pub label: u32,
+}
+
+/// Range of the attributes over all points, used to scale the properties.
+#[derive(Clone, Copy, Debug)]
+pub struct Range {
+	time: [f64; 2],
+	intensity: u16,
+	color: u16,
+	return_number: u8,
+	number_of_returns: u8,
+	classification: u8,
+}
+
+impl Range {
+	pub fn new() -> Self {
+		Self {
+			time: [f64::MAX, f64::MIN],
+			intensity: 0,
+			color: 0,
+			return_number: 0,
+			number_of_returns: 0,
+			classification: 0,
+		}
+	}
+
+	pub fn insert(&mut self, attributes: &Attributes) {
+		self.time[0] = self.time[0].min(attributes.time);
+		self.time[1] = self.time[1].max(attributes.time);
+		self.intensity = self.intensity.max(attributes.intensity);
+		for channel in attributes.color {
+			self.color = self.color.max(channel);
+		}
+		self.return_number = self.return_number.max(attributes.return_number);
+		self.number_of_returns = self.number_of_returns.max(attributes.number_of_returns);
+		self.classification = self.classification.max(attributes.classification);
+	}
+
+	/// Maximum property value for the attribute.
+	pub fn scale(&self, attribute: Attribute) -> u32 {
+		match attribute {
+			Attribute::Intensity => self.intensity as u32,
+			Attribute::ReturnNumber => self.return_number as u32,
+			Attribute::NumberOfReturns => self.number_of_returns as u32,
+			Attribute::Classification => self.classification as u32,
+			Attribute::Color | Attribute::Time => u32::MAX,
+		}
+		.max(1)
+	}
+
+	/// Property values for all attributes.
+	///
+	/// The color is packed as `0x00RRGGBB`. Some files store 8-bit colors, so the
+	/// channels are only reduced if any value is larger than `u8::MAX`.
+	pub fn properties(&self, attributes: &Attributes) -> [u32; ATTRIBUTES] {
+		let shift = if self.color > u8::MAX as u16 { 8 } else { 0 };
+		let [r, g, b] = attributes.color.map(|channel| (channel >> shift) as u32);
+		let time = if self.time[1] > self.time[0] {
+			map_to_u32(((attributes.time - self.time[0]) / (self.time[1] - self.time[0])) as f32)
+		} else {
+			0
+		};
+
+		[
+			attributes.intensity as u32,
+			attributes.return_number as u32,
+			attributes.number_of_returns as u32,
+			attributes.classification as u32,
+			(r << 16) | (g << 8) | b,
+			time,
+		]
+	}
+}
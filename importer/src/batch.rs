@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+
+use crate::{source, writer, Error, Explicit, Importer, Options, Terminal};
+
+#[derive(clap::Parser)]
+pub struct BatchArguments {
+	/// Folder with the input files, subfolders are included
+	input_folder: PathBuf,
+
+	/// Folder for the projects, with one project folder per input file named like the file
+	output_folder: PathBuf,
+
+	/// Only import files matching any glob pattern, relative to the input folder
+	#[arg(long)]
+	include: Vec<glob::Pattern>,
+
+	/// Skip files matching any glob pattern, relative to the input folder
+	#[arg(long)]
+	exclude: Vec<glob::Pattern>,
+
+	#[command(flatten)]
+	options: Options,
+}
+
+/// Command line arguments for the batch import.
+pub type BatchCommand = Explicit<BatchArguments>;
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+	Imported,
+	Skipped,
+	Failed,
+}
+
+#[derive(serde::Serialize)]
+struct Entry {
+	input: PathBuf,
+	output: PathBuf,
+	status: Status,
+	error: Option<String>,
+	statistics: Option<serde_json::Value>,
+}
+
+/// Import every file in the input folder into its own project.
+///
+/// Files with a finished project are skipped and failed imports don't stop the batch.
+/// Unfinished projects of failed or interrupted imports are removed and imported again.
+/// The summary is printed and saved as `summary.json` in the output folder.
+pub fn batch(command: BatchCommand) -> Result<(), Error> {
+	let settings = command.arguments.options.settings(&command.explicit)?;
+	let arguments = command.arguments;
+
+	let mut files = Vec::new();
+	collect(&arguments.input_folder, Path::new(""), &mut files)?;
+	files.retain(|file| {
+		(arguments.include.is_empty()
+			|| arguments
+				.include
+				.iter()
+				.any(|pattern| pattern.matches_path(file)))
+			&& !arguments
+				.exclude
+				.iter()
+				.any(|pattern| pattern.matches_path(file))
+	});
+	files.sort();
+	if files.is_empty() {
+		return Err(Error::NoInputFile);
+	}
+	std::fs::create_dir_all(&arguments.output_folder)?;
+
+	let mut entries = Vec::with_capacity(files.len());
+	for (index, file) in files.iter().enumerate() {
+		println!("[{}/{}] {}", index + 1, files.len(), file.display());
+		let input = arguments.input_folder.join(file);
+		// the extension stays in the folder name, so `plot.las` and `plot.laz` get their own projects
+		let output = arguments.output_folder.join(file);
+
+		let entry = if is_finished(&output) {
+			println!("Skipped, project already exists");
+			let statistics = std::fs::read_to_string(output.join("statistics.json"))
+				.ok()
+				.and_then(|content| serde_json::from_str(&content).ok());
+			Entry {
+				input,
+				output,
+				status: Status::Skipped,
+				error: None,
+				statistics,
+			}
+		} else {
+			let result = remove_unfinished(&output).and_then(|()| {
+				Importer::new(vec![input.clone()], output.clone())
+					.settings(settings.clone())
+					.text_format(arguments.options.text.clone())
+					.threads(arguments.options.max_threads)
+					.run(&Terminal)
+			});
+			match result {
+				Ok(statistics) => Entry {
+					input,
+					output,
+					status: Status::Imported,
+					error: None,
+					statistics: serde_json::to_value(&statistics).ok(),
+				},
+				Err(err) => {
+					println!("Error: {}", err);
+					Entry {
+						input,
+						output,
+						status: Status::Failed,
+						error: Some(err.to_string()),
+						statistics: None,
+					}
+				},
+			}
+		};
+		entries.push(entry);
+	}
+
+	print_summary(&files, &entries);
+	let file = std::fs::File::create(arguments.output_folder.join("summary.json"))?;
+	serde_json::to_writer_pretty(file, &entries).map_err(std::io::Error::from)?;
+
+	let failed = entries
+		.iter()
+		.filter(|entry| matches!(entry.status, Status::Failed))
+		.count();
+	if failed > 0 {
+		return Err(Error::BatchFailed(failed));
+	}
+	Ok(())
+}
+
+/// Supported files in the folder and all subfolders, relative to the input folder.
+fn collect(folder: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+	for entry in std::fs::read_dir(folder.join(relative))? {
+		let entry = entry?;
+		let relative = relative.join(entry.file_name());
+		let file_type = entry.file_type()?;
+		if file_type.is_dir() {
+			collect(folder, &relative, files)?;
+		} else if file_type.is_file() && source::is_supported(&relative) {
+			files.push(relative);
+		}
+	}
+	Ok(())
+}
+
+/// The statistics are saved last, so the project is complete if they exist.
+fn is_finished(output: &Path) -> bool {
+	output.join("project.epc").exists() && output.join("statistics.json").exists()
+}
+
+/// The importer only replaces folders with a project, so the files of an unfinished import are removed first.
+///
+/// Only folders with the marker of the importer are removed, other folders at the same path are an error.
+fn remove_unfinished(output: &Path) -> Result<(), Error> {
+	if !output.is_dir() || output.read_dir()?.next().is_none() {
+		return Ok(());
+	}
+	if !output.join(writer::MARKER).is_file() {
+		return Err(Error::ForeignOutputFolder(output.to_owned()));
+	}
+	std::fs::remove_dir_all(output)?;
+	Ok(())
+}
+
+fn print_summary(files: &[PathBuf], entries: &[Entry]) {
+	let width = files
+		.iter()
+		.map(|file| file.display().to_string().len())
+		.max()
+		.unwrap_or_default()
+		.max(4);
+
+	println!();
+	println!(
+		"{:<width$}  {:<8}  {:>12}  {:>8}  {:>10}",
+		"File", "Status", "Points", "Segments", "Seconds"
+	);
+	for (file, entry) in files.iter().zip(entries) {
+		let status = match entry.status {
+			Status::Imported => "imported",
+			Status::Skipped => "skipped",
+			Status::Failed => "failed",
+		};
+		let value = |key: &str| {
+			entry
+				.statistics
+				.as_ref()
+				.and_then(|statistics| statistics[key].as_u64())
+				.map(|value| value.to_string())
+				.unwrap_or_default()
+		};
+		let seconds = entry
+			.statistics
+			.as_ref()
+			.and_then(|statistics| statistics["times"].as_object())
+			.map(|times| {
+				let seconds = times.values().filter_map(|time| time.as_f64()).sum::<f64>();
+				format!("{:.1}", seconds)
+			})
+			.unwrap_or_default();
+		println!(
+			"{:<width$}  {:<8}  {:>12}  {:>8}  {:>10}",
+			file.display().to_string(),
+			status,
+			value("source_points"),
+			value("segments"),
+			seconds,
+		);
+	}
+}
@@ -3,15 +3,64 @@ use std::{
 	fs::File,
 	io::{Read, Seek, Write},
 	mem::MaybeUninit,
-	ops::Not,
+	path::PathBuf,
 };
 
+use crate::Error;
+
+/// Entries with fewer bytes stay in memory, so small segments don't each get a spill file.
+const MIN_SPILL_SIZE: usize = 64 * 1024;
+
+/// Storage for the points during the import, entries exceeding the budget are spilled to files.
 pub struct Cache {
-	active: HashMap<usize, (Vec<u8>, usize)>,
+	active: HashMap<usize, Active>,
 	stored: HashMap<usize, (File, usize)>,
 	current: usize,
 	max_values: usize,
+	/// Memory to search for entries to spill, above the budget if only small entries are left.
+	evict_at: usize,
 	entry_index: usize,
+	appends: usize,
+	peak: usize,
+	spilled: usize,
+	spill_folder: Option<PathBuf>,
+}
+
+/// Values of an entry in memory, the vector is allocated with the type of the values.
+struct Active {
+	data: Vec<u8>,
+	/// Size of a value in bytes.
+	size: usize,
+	/// Append count at the last append.
+	appended: usize,
+	/// Drop the vector with the type of the values.
+	free: fn(Vec<u8>),
+}
+
+impl Active {
+	fn new<T>(data: Vec<T>, appended: usize) -> Self {
+		Self {
+			data: unsafe { std::mem::transmute::<Vec<T>, Vec<u8>>(data) },
+			size: std::mem::size_of::<T>(),
+			appended,
+			free: free::<T>,
+		}
+	}
+
+	/// Values of the entry, `T` must be the type of the entry.
+	unsafe fn values<T>(&mut self) -> &mut Vec<T> {
+		std::mem::transmute::<&mut Vec<u8>, &mut Vec<T>>(&mut self.data)
+	}
+
+	/// Bytes in memory, the vector only counts values.
+	fn bytes(&self) -> usize {
+		self.data.capacity() * self.size
+	}
+}
+
+/// The allocator requires the same size and alignment as for the allocation.
+fn free<T>(data: Vec<u8>) {
+	drop(unsafe { std::mem::transmute::<Vec<u8>, Vec<T>>(data) });
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -25,109 +74,151 @@ pub struct CacheEntry<T> {
 }
 
 impl Cache {
-	pub fn new(max_values: usize) -> Self {
+	/// Cache with a budget in bytes, spilled entries are saved in the folder or the system temp folder.
+	pub fn new(max_values: usize, spill_folder: Option<PathBuf>) -> Self {
 		Self {
 			active: HashMap::new(),
 			stored: HashMap::new(),
 			current: 0,
 			max_values,
+			evict_at: max_values,
 			entry_index: 0,
+			appends: 0,
+			peak: 0,
+			spilled: 0,
+			spill_folder,
 		}
 	}
 
+	/// Most bytes held in memory at once.
+	pub fn peak(&self) -> usize {
+		self.peak
+	}
+
+	/// Most bytes held in memory above the budget.
+	pub fn over_budget(&self) -> usize {
+		self.peak.saturating_sub(self.max_values)
+	}
+
+	/// Bytes written to the spill files.
+	pub fn spilled(&self) -> usize {
+		self.spilled
+	}
+
 	pub fn new_entry<T>(&mut self) -> CacheIndex<T> {
 		self.entry_index += 1;
 		CacheIndex(self.entry_index, std::marker::PhantomData)
 	}
 
-	pub fn add_value<T>(&mut self, index: &CacheIndex<T>, value: T) {
+	pub fn add_value<T>(&mut self, index: &CacheIndex<T>, value: T) -> Result<(), Error> {
+		self.appends += 1;
 		match self.active.get_mut(&index.0) {
 			None => {
-				let vec = vec![value];
-				self.current += vec.capacity() * std::mem::size_of::<T>();
-				self.active.insert(
-					index.0,
-					(
-						unsafe { std::mem::transmute(vec) },
-						std::mem::size_of::<T>(),
-					),
-				);
+				let entry = Active::new(vec![value], self.appends);
+				self.current += entry.bytes();
+				self.active.insert(index.0, entry);
 			},
 			Some(entry) => {
-				let data = unsafe { std::mem::transmute::<_, &mut Vec<T>>(&mut entry.0) };
-				let c = data.capacity();
-				data.push(value);
-				self.current += entry.1 * (data.capacity() - c);
+				let c = entry.bytes();
+				unsafe { entry.values().push(value) };
+				self.current += entry.bytes() - c;
+				entry.appended = self.appends;
 			},
 		}
 
-		if self.current >= self.max_values {
-			self.evict();
+		self.peak = self.peak.max(self.current);
+		if self.current >= self.evict_at {
+			self.evict()?;
 		}
+		Ok(())
 	}
 
-	pub fn add_chunk<T>(&mut self, index: &CacheIndex<T>, mut chunk: Vec<T>) {
+	pub fn add_chunk<T>(&mut self, index: &CacheIndex<T>, mut chunk: Vec<T>) -> Result<(), Error> {
+		self.appends += 1;
 		match self.active.get_mut(&index.0) {
 			None => {
-				let vec = chunk;
-				self.current += vec.capacity() * std::mem::size_of::<T>();
-				self.active.insert(
-					index.0,
-					(
-						unsafe { std::mem::transmute(vec) },
-						std::mem::size_of::<T>(),
-					),
-				);
+				let entry = Active::new(chunk, self.appends);
+				self.current += entry.bytes();
+				self.active.insert(index.0, entry);
 			},
 			Some(entry) => {
-				let data = unsafe { std::mem::transmute::<_, &mut Vec<T>>(&mut entry.0) };
-				let c = data.capacity();
-				data.append(&mut chunk);
-				self.current += entry.1 * (data.capacity() - c);
+				let c = entry.bytes();
+				unsafe { entry.values().append(&mut chunk) };
+				self.current += entry.bytes() - c;
+				entry.appended = self.appends;
 			},
 		}
 
-		if self.current >= self.max_values {
-			self.evict();
+		self.peak = self.peak.max(self.current);
+		if self.current >= self.evict_at {
+			self.evict()?;
 		}
+		Ok(())
 	}
 
-	fn evict(&mut self) {
-		let Some((&key, _)) = self
-			.active
-			.iter()
-			.max_by_key(|(_, entry)| entry.0.len() * entry.1)
-		else {
-			return;
-		};
+	/// Spill the least recently appended entries, until a quarter of the budget is free.
+	///
+	/// Entries still being filled stay in memory and freeing more than necessary
+	/// avoids searching the entries on every append. If only small entries are left,
+	/// the budget is exceeded and the next search waits for another quarter of the budget.
+	fn evict(&mut self) -> Result<(), Error> {
+		while self.current > self.max_values / 4 * 3 {
+			let Some((&key, _)) = self
+				.active
+				.iter()
+				.filter(|(_, entry)| entry.bytes() >= MIN_SPILL_SIZE)
+				.min_by_key(|(_, entry)| entry.appended)
+			else {
+				self.evict_at = self.current.max(self.max_values) + self.max_values / 4;
+				return Ok(());
+			};
+			self.spill(key)?;
+		}
+		self.evict_at = self.max_values;
+		Ok(())
+	}
 
-		fn write_to(file: &mut File, data: Vec<u8>, size: usize) {
-			unsafe {
-				let view = std::slice::from_raw_parts(data.as_ptr(), size * data.len());
-				file.write_all(view).unwrap();
-			}
+	fn spill(&mut self, key: usize) -> Result<(), Error> {
+		fn write_to(file: &mut File, entry: Active) -> Result<(), Error> {
+			let view = unsafe { std::slice::from_raw_parts(entry.data.as_ptr(), entry.size * entry.data.len()) };
+			let result = file.write_all(view).map_err(Error::Spill);
+			(entry.free)(entry.data);
+			result
 		}
 		let entry = self.active.remove(&key).unwrap();
-		self.current -= entry.0.capacity() * entry.1;
+		self.current -= entry.bytes();
+		self.spilled += entry.data.len() * entry.size;
 
 		match self.stored.get_mut(&key) {
 			None => {
-				let mut file = tempfile::tempfile().unwrap();
-				let l = entry.0.len();
-				write_to(&mut file, entry.0, entry.1);
+				let mut file = match &self.spill_folder {
+					Some(folder) => tempfile::tempfile_in(folder),
+					None => tempfile::tempfile(),
+				}
+				.map_err(Error::Spill)?;
+				let l = entry.data.len();
+				write_to(&mut file, entry)?;
 				self.stored.insert(key, (file, l));
 			},
 			Some((file, length)) => {
-				*length += entry.0.len();
-				write_to(file, entry.0, entry.1);
+				*length += entry.data.len();
+				write_to(file, entry)?;
 			},
 		}
+		Ok(())
 	}
 
 	pub fn read<T>(&mut self, index: CacheIndex<T>) -> CacheEntry<T> {
-		let active = self.active.remove(&index.0).unwrap_or_default();
-		let active = unsafe { std::mem::transmute::<_, Vec<T>>(active.0) };
-		self.current -= active.capacity() * std::mem::size_of::<T>();
+		let active = match self.active.remove(&index.0) {
+			Some(entry) => {
+				self.current -= entry.bytes();
+				unsafe { std::mem::transmute::<Vec<u8>, Vec<T>>(entry.data) }
+			},
+			None => Vec::new(),
+		};
+		if self.current < self.max_values {
+			self.evict_at = self.max_values;
+		}
 		let (file, length) = if let Some((file, length)) = self.stored.remove(&index.0) {
 			(Some(file), length + active.len())
 		} else {
@@ -139,7 +230,7 @@ impl Cache {
 	pub fn size<T>(&mut self, index: &CacheIndex<T>) -> usize {
 		self.active
 			.get(&index.0)
-			.map(|active| active.0.len())
+			.map(|active| active.data.len())
 			.unwrap_or_default()
 			+ self
 				.stored
@@ -149,31 +240,69 @@ impl Cache {
 	}
 }
 
-impl<T> CacheEntry<T> {
-	pub fn read(mut self) -> Vec<T> {
-		if let Some(mut file) = self.file {
-			unsafe {
-				let mut data = Vec::<MaybeUninit<T>>::new();
-				let l = file.metadata().unwrap().len() as usize / std::mem::size_of::<T>();
-				data.reserve(l + self.active.len());
-				data.set_len(l);
-				let view = std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, std::mem::size_of::<T>() * l);
-				file.seek(std::io::SeekFrom::Start(0)).unwrap();
-				file.read_exact(view).unwrap();
-				let mut data = std::mem::transmute::<_, Vec<T>>(data);
-				data.append(&mut self.active);
-				data
-			}
-		} else {
-			self.active
+impl Drop for Cache {
+	fn drop(&mut self) {
+		for (_, entry) in self.active.drain() {
+			(entry.free)(entry.data);
 		}
 	}
+}
 
-	pub fn active(&self) -> bool {
-		self.active.is_empty().not()
+impl<T> CacheEntry<T> {
+	/// All values, the spilled ones are loaded from the file.
+	pub fn read(mut self) -> Result<Vec<T>, Error> {
+		let Some(mut file) = self.file else {
+			return Ok(self.active);
+		};
+		let l = file.metadata().map_err(Error::Unspill)?.len() as usize / std::mem::size_of::<T>();
+		let mut data = Vec::<MaybeUninit<T>>::with_capacity(l + self.active.len());
+		unsafe {
+			let view = std::slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, std::mem::size_of::<T>() * l);
+			file.seek(std::io::SeekFrom::Start(0))
+				.map_err(Error::Unspill)?;
+			file.read_exact(view).map_err(Error::Unspill)?;
+			data.set_len(l);
+			let mut data = std::mem::transmute::<Vec<MaybeUninit<T>>, Vec<T>>(data);
+			data.append(&mut self.active);
+			Ok(data)
+		}
 	}
 
 	pub fn length(&self) -> usize {
 		self.length
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn spill_and_read() {
+		let mut cache = Cache::new(100_000, None);
+		let entries = (0..3)
+			.map(|_| cache.new_entry())
+			.collect::<Vec<CacheIndex<[u64; 3]>>>();
+		for value in 0..10_000u64 {
+			for (index, entry) in entries.iter().enumerate() {
+				cache
+					.add_value(entry, [value, index as u64, u64::MAX - value])
+					.unwrap();
+			}
+		}
+		let chunk = (10_000..12_000)
+			.map(|value| [value, 0, u64::MAX - value])
+			.collect();
+		cache.add_chunk(&entries[0], chunk).unwrap();
+		assert!(cache.spilled() > 0);
+
+		for (index, entry) in entries.into_iter().enumerate() {
+			let values = cache.read(entry).read().unwrap();
+			let length = if index == 0 { 12_000 } else { 10_000 };
+			assert_eq!(values.len(), length);
+			for (value, read) in values.into_iter().enumerate() {
+				assert_eq!(read, [value as u64, index as u64, u64::MAX - value as u64]);
+			}
+		}
+	}
+}
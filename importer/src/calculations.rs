@@ -2,7 +2,7 @@ use std::num::NonZeroU32;
 
 use math::{Dimension, Mat, Vector, X, Y, Z};
 
-use crate::{point::Point, Settings};
+use crate::{attributes, ground::Dtm, point::Point, source::SourcePoint, Settings};
 
 pub struct SegmentInformation {
 	pub trunk_height: project::Value,
@@ -10,33 +10,38 @@ pub struct SegmentInformation {
 }
 
 pub fn calculate(
-	data: Vec<Vector<3, f32>>,
+	data: Vec<SourcePoint>,
 	segment: NonZeroU32,
 	settings: &Settings,
+	range: &attributes::Range,
+	ground: Option<&Dtm>,
 ) -> (Vec<Point>, SegmentInformation) {
+	let (data, attributes): (Vec<_>, Vec<_>) = data
+		.into_iter()
+		.map(|point| (point.position, point.attributes))
+		.unzip();
 	let neighbors_tree = NeighborsTree::new(&data);
 
-	let (min, max) = {
-		let mut min = data[0][Y];
-		let mut max = data[0][Y];
-		for p in data.iter().skip(1) {
-			if p[Y] < min {
-				min = p[Y];
-			} else if p[Y] > max {
-				max = p[Y];
-			}
-		}
-		(min, max)
+	// height above the ground or the lowest point
+	let heights = match ground {
+		Some(dtm) => data
+			.iter()
+			.map(|&p| dtm.above(p).max(0.0))
+			.collect::<Vec<_>>(),
+		None => {
+			let min = data.iter().map(|p| p[Y]).fold(f32::MAX, f32::min);
+			data.iter().map(|p| p[Y] - min).collect::<Vec<_>>()
+		},
 	};
-	let height = max - min;
+	let height = heights.iter().copied().fold(0.0, f32::max);
 
 	let (slices, slice_width, trunk_crown_sep) = {
 		let slice_width = 0.05;
 
 		let slices = ((height / slice_width).ceil() as usize) + 1;
 		let mut means = vec![(Vector::new([0.0, 0.0]), 0); slices];
-		for pos in data.iter().copied() {
-			let idx = ((pos[Y] - min) / slice_width) as usize;
+		for (pos, height) in data.iter().copied().zip(heights.iter().copied()) {
+			let idx = (height / slice_width) as usize;
 			means[idx].0 += [pos[X], pos[Z]].into();
 			means[idx].1 += 1;
 		}
@@ -44,8 +49,8 @@ pub fn calculate(
 			mean.0 /= mean.1 as f32;
 		}
 		let mut variance = vec![0.0f32; slices];
-		for pos in data.iter().copied() {
-			let idx = ((pos[Y] - min) / slice_width) as usize;
+		for (pos, height) in data.iter().copied().zip(heights.iter().copied()) {
+			let idx = (height / slice_width) as usize;
 			variance[idx] += (means[idx].0 - [pos[X], pos[Z]].into()).length_squared();
 		}
 		let mut max_var = 0.0;
@@ -70,7 +75,7 @@ pub fn calculate(
 			.map(|(index, _)| index)
 			.unwrap_or(0);
 
-		(mapped, slice_width, min + slice_width * sep as f32)
+		(mapped, slice_width, slice_width * sep as f32)
 	};
 	let mut neighbors_location = bytemuck::zeroed_vec(settings.neighbors_count);
 
@@ -118,22 +123,29 @@ pub fn calculate(
 				.sum::<f32>();
 			let size = size / (neighbors.len() - 1) as f32 / 2.0;
 
+			// isolated points have no surface for the normal and the size
+			let (normal, size) = if neighbors.len() < 2 {
+				(
+					Vector::new([0.0, 1.0, 0.0]),
+					settings.neighbors_max_distance / 2.0,
+				)
+			} else {
+				(eigen_vectors[Z], size)
+			};
+
 			Point {
-				render: project::Point {
-					position: data[i],
-					normal: eigen_vectors[Z],
-					size,
-				},
+				render: project::Point { position: data[i], normal, size },
 				segment,
-				slice: slices[((data[i][Y] - min) / slice_width) as usize],
-				height: ((data[i][Y] - min) / (max - min) * u32::MAX as f32) as u32,
+				slice: slices[(heights[i] / slice_width) as usize],
+				height: (heights[i] / height * u32::MAX as f32) as u32,
 				curve: map_to_u32((3.0 * eigen_values[Z]) / (eigen_values[X] + eigen_values[Y] + eigen_values[Z])),
+				attributes: range.properties(&attributes[i]),
 			}
 		})
 		.collect::<Vec<Point>>();
 
-	let trunk_heigth = trunk_crown_sep - min;
-	let crown_heigth = max - trunk_crown_sep;
+	let trunk_heigth = trunk_crown_sep;
+	let crown_heigth = height - trunk_crown_sep;
 	(
 		res,
 		SegmentInformation {
@@ -183,6 +195,13 @@ impl NeighborsTree {
 		let l = self.tree.k_nearest(&data[index], location, max_distance);
 		&location[0..l]
 	}
+
+	/// Index of the nearest point within the squared distance.
+	pub fn nearest(&self, position: Vector<3, f32>, max_distance: f32) -> Option<usize> {
+		let mut location = [bytemuck::Zeroable::zeroed()];
+		let l = self.tree.k_nearest(&position, &mut location, max_distance);
+		(l > 0).then_some(location[0].index)
+	}
 }
 
 pub fn map_to_u32(value: f32) -> u32 {
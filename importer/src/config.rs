@@ -0,0 +1,226 @@
+use std::path::Path;
+
+use crate::{Error, Settings};
+
+/// Scanner types with matching settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Preset {
+	/// Airborne laser scanning, sparse points mostly from above
+	Als,
+	/// Laser scanning from drones
+	Uls,
+	/// Terrestrial laser scanning, dense points mostly from below
+	Tls,
+}
+
+impl Preset {
+	pub fn settings(self) -> Settings {
+		let mut settings = Settings::default();
+		match self {
+			Self::Als => {
+				settings.min_segment_size = 20;
+				settings.segmenting_slice_width = 2.0;
+				settings.segmenting_max_distance = 2.0;
+				settings.neighbors_count = 15;
+				settings.neighbors_max_distance = 3.0;
+				settings.lod_size_scale = 1.0;
+				settings.ground_filter = true;
+			},
+			Self::Uls => {
+				settings.ground_filter = true;
+			},
+			Self::Tls => {
+				settings.min_segment_size = 1000;
+				settings.segmenting_slice_width = 0.5;
+				settings.segmenting_max_distance = 0.5;
+				settings.neighbors_max_distance = 0.2;
+				settings.voxel_size = Some(0.01);
+				settings.statistical_outlier_filter = true;
+				settings.ground_filter = true;
+			},
+		}
+		settings
+	}
+}
+
+/// Arguments to turn off the filters and downsampling of the preset or settings file.
+#[derive(clap::Args)]
+pub struct Disable {
+	/// Don't classify the ground
+	#[arg(long, overrides_with = "ground_filter")]
+	no_ground_filter: bool,
+
+	/// Don't remove statistical outliers
+	#[arg(long, overrides_with = "statistical_outlier_filter")]
+	no_statistical_outlier_filter: bool,
+
+	/// Don't remove radius outliers
+	#[arg(long, overrides_with = "radius_outlier_filter")]
+	no_radius_outlier_filter: bool,
+
+	/// Don't keep only one point per voxel
+	#[arg(long, overrides_with = "voxel_size")]
+	no_voxel_size: bool,
+
+	/// Don't enforce a minimum distance between the points
+	#[arg(long, overrides_with = "min_spacing")]
+	no_min_spacing: bool,
+}
+
+impl Disable {
+	/// Turn off the selected values, after the preset, settings file and arguments are combined.
+	pub fn apply(&self, settings: &mut Settings) {
+		if self.no_ground_filter {
+			settings.ground_filter = false;
+		}
+		if self.no_statistical_outlier_filter {
+			settings.statistical_outlier_filter = false;
+		}
+		if self.no_radius_outlier_filter {
+			settings.radius_outlier_filter = false;
+		}
+		if self.no_voxel_size {
+			settings.voxel_size = None;
+		}
+		if self.no_min_spacing {
+			settings.min_spacing = None;
+		}
+	}
+}
+
+/// Combine the preset, the settings file and the arguments.
+///
+/// Later sources take precedence, but only the arguments in `explicit` are used, all others
+/// are the clap defaults.
+pub fn resolve(
+	arguments: &Settings,
+	explicit: &[String],
+	preset: Option<Preset>,
+	path: Option<&Path>,
+) -> Result<Settings, Error> {
+	let mut settings = to_value(&preset.map(Preset::settings).unwrap_or_default())?;
+	if let Some(path) = path {
+		let serde_json::Value::Object(values) = load(path)? else {
+			return Err(Error::InvalidConfig(format!(
+				"expected a table in '{}'",
+				path.display()
+			)));
+		};
+		for (key, value) in values {
+			settings[key] = value;
+		}
+	}
+	let arguments = to_value(arguments)?;
+	for id in explicit {
+		if let Some(value) = arguments.get(id) {
+			settings[id] = value.clone();
+		}
+	}
+	serde_json::from_value(settings).map_err(|err| Error::InvalidConfig(err.to_string()))
+}
+
+fn to_value(settings: &Settings) -> Result<serde_json::Value, Error> {
+	serde_json::to_value(settings).map_err(|err| Error::InvalidConfig(err.to_string()))
+}
+
+/// Load a `.toml` or `.json` settings file.
+fn load(path: &Path) -> Result<serde_json::Value, Error> {
+	let content = std::fs::read_to_string(path)?;
+	let extension = path
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.unwrap_or_default();
+	match extension {
+		"toml" => toml::from_str(&content).map_err(|err| Error::InvalidConfig(err.to_string())),
+		"json" => serde_json::from_str(&content).map_err(|err| Error::InvalidConfig(err.to_string())),
+		_ => Err(Error::InvalidConfig(format!(
+			"unknown settings format '{}'",
+			path.display()
+		))),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Write;
+
+	use clap::Parser;
+
+	use super::*;
+	use crate::Command;
+
+	/// Settings for the command line arguments after the input file.
+	fn resolved(arguments: &[&str]) -> Settings {
+		let command = Command::parse_from(["importer", "input.laz"].iter().chain(arguments));
+		command
+			.arguments
+			.options
+			.settings(&command.explicit)
+			.unwrap()
+	}
+
+	#[test]
+	fn precedence() {
+		let mut file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+		writeln!(file, "min_segment_size = 500").unwrap();
+		writeln!(file, "segmenting_slice_width = 0.8").unwrap();
+		writeln!(file, "segmenting_max_distance = 0.7").unwrap();
+		let config = file.path().to_str().unwrap();
+
+		let settings = resolved(&[
+			"--preset",
+			"tls",
+			"--config",
+			config,
+			"--segmenting-max-distance",
+			"0.3",
+		]);
+		// the settings file overwrites the preset
+		assert_eq!(settings.min_segment_size, 500);
+		assert_eq!(settings.segmenting_slice_width, 0.8);
+		// the explicit argument overwrites the settings file
+		assert_eq!(settings.segmenting_max_distance, 0.3);
+		// the preset overwrites the defaults of the arguments
+		assert_eq!(settings.neighbors_max_distance, 0.2);
+		assert_eq!(settings.voxel_size, Some(0.01));
+		assert!(settings.statistical_outlier_filter);
+		// neither the preset nor the settings file change the default
+		assert_eq!(settings.chm_cell_size, Settings::default().chm_cell_size);
+
+		// an explicit argument with the default value still overwrites the settings file
+		let settings = resolved(&[
+			"--preset",
+			"tls",
+			"--config",
+			config,
+			"--min-segment-size",
+			"100",
+		]);
+		assert_eq!(settings.min_segment_size, 100);
+		assert_eq!(settings.segmenting_slice_width, 0.8);
+	}
+
+	#[test]
+	fn disable() {
+		let settings = resolved(&["--preset", "tls", "--no-voxel-size", "--no-ground-filter"]);
+		assert_eq!(settings.voxel_size, None);
+		assert!(!settings.ground_filter);
+		assert!(settings.statistical_outlier_filter);
+
+		// the explicit value also turns off the filters of the arguments
+		let settings = resolved(&[
+			"--voxel-size",
+			"0.05",
+			"--no-voxel-size",
+			"--statistical-outlier-filter",
+		]);
+		assert_eq!(settings.voxel_size, None);
+		assert!(settings.statistical_outlier_filter);
+
+		// the last of the two arguments wins
+		let settings = resolved(&["--preset", "tls", "--no-ground-filter", "--ground-filter"]);
+		assert!(settings.ground_filter);
+		let settings = resolved(&["--preset", "als", "--ground-filter", "--no-ground-filter"]);
+		assert!(!settings.ground_filter);
+	}
+}
@@ -0,0 +1,140 @@
+use std::{io::BufWriter, path::PathBuf};
+
+use math::{Vector, X, Y};
+use serde_json::{json, Value};
+
+use crate::{Error, Settings};
+
+/// GeoJSON files with the steps of the slice segmentation, for `debug_segmentation`.
+///
+/// Every slice gets one file with the hulls of the point groups, the stem centroids carried to the
+/// next slice and the Voronoi cells around them, all in source coordinates.
+#[derive(Clone)]
+pub struct SegmentationDebug {
+	folder: PathBuf,
+	prefix: String,
+	georeference: project::Georeference,
+}
+
+/// Shapes of one slice, with the horizontal positions in importer coordinates.
+pub struct SliceDebug {
+	pub index: usize,
+	pub min_height: f32,
+	pub max_height: f32,
+	pub hulls: Vec<Vec<Vector<2, f32>>>,
+	/// Centroid and if it was carried from the previous slice without a match.
+	pub centroids: Vec<(Vector<2, f32>, bool)>,
+	/// Voronoi cell and the count of the assigned points.
+	pub cells: Vec<(Vec<Vector<2, f32>>, usize)>,
+}
+
+impl SegmentationDebug {
+	/// Creates the folder, `None` without `debug_segmentation`.
+	pub fn new(settings: &Settings, georeference: &project::Georeference) -> Result<Option<Self>, Error> {
+		let Some(folder) = &settings.debug_segmentation else {
+			return Ok(None);
+		};
+		std::fs::create_dir_all(folder)?;
+		Ok(Some(Self {
+			folder: folder.clone(),
+			prefix: String::new(),
+			georeference: georeference.clone(),
+		}))
+	}
+
+	/// Separate file names for each tile of the tiled segmentation.
+	pub fn for_tile(&self, column: usize, row: usize) -> Self {
+		Self {
+			prefix: format!("tile_{}_{}_", column, row),
+			..self.clone()
+		}
+	}
+
+	pub fn save(&self, slice: &SliceDebug) -> Result<(), Error> {
+		let polygon = |points: &[Vector<2, f32>]| {
+			let mut ring = points
+				.iter()
+				.map(|&point| self.position(point))
+				.collect::<Vec<_>>();
+			// GeoJSON rings are closed
+			if let Some(&first) = ring.first() {
+				ring.push(first);
+			}
+			json!({ "type": "Polygon", "coordinates": [ring] })
+		};
+
+		let mut features = Vec::new();
+		for (id, hull) in slice.hulls.iter().enumerate() {
+			features.push(self.feature(slice, "hull", id, polygon(hull), json!({})));
+		}
+		for (id, &(centroid, carried)) in slice.centroids.iter().enumerate() {
+			let point = json!({ "type": "Point", "coordinates": self.position(centroid) });
+			features.push(self.feature(slice, "centroid", id, point, json!({ "carried": carried })));
+		}
+		for (id, (cell, points)) in slice.cells.iter().enumerate() {
+			if cell.is_empty() {
+				continue;
+			}
+			features.push(self.feature(
+				slice,
+				"cell",
+				id,
+				polygon(cell),
+				json!({ "points": points }),
+			));
+		}
+
+		let mut collection = json!({
+			"type": "FeatureCollection",
+			"name": format!("{}slice_{}", self.prefix, slice.index),
+			"features": features,
+		});
+		// only EPSG codes have a name GIS tools understand
+		if let Some(code) = self
+			.georeference
+			.crs
+			.as_deref()
+			.and_then(|crs| crs.strip_prefix("EPSG:"))
+		{
+			collection["crs"] = json!({
+				"type": "name",
+				"properties": { "name": format!("urn:ogc:def:crs:EPSG::{}", code) },
+			});
+		}
+
+		let path = self.path(slice.index);
+		let file = BufWriter::new(std::fs::File::create(path)?);
+		serde_json::to_writer(file, &collection).map_err(std::io::Error::from)?;
+		Ok(())
+	}
+
+	fn path(&self, index: usize) -> PathBuf {
+		self.folder
+			.join(format!("{}slice_{:03}.geojson", self.prefix, index))
+	}
+
+	fn position(&self, position: Vector<2, f32>) -> [f64; 2] {
+		let source = self
+			.georeference
+			.to_source(Vector::new([position[X], 0.0, position[Y]]));
+		[source[X], source[Y]]
+	}
+
+	fn feature(&self, slice: &SliceDebug, kind: &str, id: usize, geometry: Value, extra: Value) -> Value {
+		let mut properties = json!({
+			"kind": kind,
+			"id": id,
+			"slice": slice.index,
+			"min_height": slice.min_height,
+			"max_height": slice.max_height,
+		});
+		if let (Value::Object(properties), Value::Object(extra)) = (&mut properties, extra) {
+			properties.extend(extra);
+		}
+		json!({
+			"type": "Feature",
+			"geometry": geometry,
+			"properties": properties,
+		})
+	}
+}
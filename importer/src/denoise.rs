@@ -0,0 +1,415 @@
+use std::collections::BTreeMap;
+
+use math::{Vector, X, Z};
+use rayon::prelude::*;
+
+use crate::{
+	cache::{Cache, CacheIndex},
+	calculations::NeighborsTree,
+	progress::{Observer, Progress},
+	source::SourcePoint,
+	Error, Settings, Statistics,
+};
+
+/// Horizontal size of the tiles in meters.
+const TILE_SIZE: f32 = 10.0;
+
+/// Largest halo in meters for the nearest neighbors of the statistical filter.
+/// Tiles with less points than neighbors get this halo.
+const MAX_STATISTICAL_HALO: f32 = TILE_SIZE;
+
+/// Points of a tile and the points of the neighboring tiles inside the halo.
+struct Tile {
+	points: CacheIndex<SourcePoint>,
+	halo: CacheIndex<SourcePoint>,
+}
+
+/// Bounds of the kept points.
+type Bounds = (Vector<3, f32>, Vector<3, f32>);
+
+/// Remove noise like birds or multipath returns from the buffered points.
+///
+/// Radius outliers have less than the required neighbors in the search radius.
+/// Statistical outliers have a mean distance to their nearest neighbors, which is larger
+/// than the mean over all points by the allowed standard deviations. The statistical filter
+/// only sees the points left by the radius filter.
+///
+/// The neighbors are searched in tiles with a halo of points from the neighboring tiles,
+/// so the memory depends on the tile size instead of the input.
+///
+/// Returns the remaining points and shrinks the bounds to them.
+pub fn denoise(
+	mut batches: Vec<CacheIndex<SourcePoint>>,
+	(min, max): (&mut Vector<3, f32>, &mut Vector<3, f32>),
+	settings: &Settings,
+	cache: &mut Cache,
+	statistics: &mut Statistics,
+	observer: &dyn Observer,
+) -> Result<Vec<CacheIndex<SourcePoint>>, Error> {
+	let total = batches.iter().map(|batch| cache.size(batch)).sum::<usize>();
+	let filters = settings.radius_outlier_filter as usize + settings.statistical_outlier_filter as usize;
+	let mut progress = Progress::new("Denoise", total * filters, observer);
+
+	let mut kept = (Vector::new([f32::MAX; 3]), Vector::new([f32::MIN; 3]));
+	if settings.radius_outlier_filter {
+		batches = radius_filter(
+			batches,
+			settings,
+			cache,
+			statistics,
+			&mut progress,
+			&mut kept,
+		)?;
+	}
+	if settings.statistical_outlier_filter {
+		batches = statistical_filter(
+			batches,
+			settings,
+			cache,
+			statistics,
+			&mut progress,
+			&mut kept,
+		)?;
+	}
+	statistics.times.denoise = progress.finish();
+
+	if kept.0[X] <= kept.1[X] {
+		(*min, *max) = kept;
+	}
+	Ok(batches)
+}
+
+/// Remove the points with less than the required neighbors in the search radius.
+fn radius_filter(
+	batches: Vec<CacheIndex<SourcePoint>>,
+	settings: &Settings,
+	cache: &mut Cache,
+	statistics: &mut Statistics,
+	progress: &mut Progress,
+	kept: &mut Bounds,
+) -> Result<Vec<CacheIndex<SourcePoint>>, Error> {
+	let distance = settings.radius_outlier_distance;
+	let tiles = split(batches, |_| distance, distance, cache)?;
+
+	*kept = (Vector::new([f32::MAX; 3]), Vector::new([f32::MIN; 3]));
+	let mut batches = Vec::with_capacity(tiles.len());
+	for tile in tiles.into_values() {
+		let (mut points, positions) = read(tile, cache)?;
+		if points.is_empty() {
+			continue;
+		}
+		let tree = NeighborsTree::new(&positions);
+		let outlier = (0..points.len())
+			.into_par_iter()
+			.map_init(
+				|| bytemuck::zeroed_vec(settings.radius_outlier_neighbors + 1),
+				|location, index| {
+					let neighbors = tree.get(index, &positions, location, distance * distance);
+					// the point itself is always the nearest neighbor
+					neighbors.len() <= settings.radius_outlier_neighbors
+				},
+			)
+			.collect::<Vec<_>>();
+		drop(tree);
+		progress.step_by(points.len());
+
+		let mut outlier = outlier.into_iter();
+		points.retain(|point| {
+			let keep = !outlier.next().unwrap();
+			if keep {
+				insert(kept, point);
+			} else {
+				statistics.radius_outliers += 1;
+			}
+			keep
+		});
+		if points.is_empty() {
+			continue;
+		}
+		let batch = cache.new_entry();
+		cache.add_chunk(&batch, points)?;
+		batches.push(batch);
+	}
+	Ok(batches)
+}
+
+/// Remove the points with a large mean distance to their nearest neighbors.
+///
+/// The halo of a tile reaches as far as the nearest neighbors of its points inside the tile
+/// alone. Other points can only be nearer, so the points near the tile edge get the same
+/// neighbors as without tiles.
+fn statistical_filter(
+	batches: Vec<CacheIndex<SourcePoint>>,
+	settings: &Settings,
+	cache: &mut Cache,
+	statistics: &mut Statistics,
+	progress: &mut Progress,
+	kept: &mut Bounds,
+) -> Result<Vec<CacheIndex<SourcePoint>>, Error> {
+	let neighbors = settings.statistical_outlier_neighbors;
+
+	// the halo for every tile from the neighbors inside the tile
+	let tiles = split(batches, |_| 0.0, 0.0, cache)?;
+	let mut halos = BTreeMap::new();
+	let mut batches = Vec::with_capacity(tiles.len());
+	for (key, tile) in tiles {
+		let (points, positions) = read(tile, cache)?;
+		if points.is_empty() {
+			continue;
+		}
+		let tree = NeighborsTree::new(&positions);
+		let halo = (0..points.len())
+			.into_par_iter()
+			.map_init(
+				|| bytemuck::zeroed_vec(neighbors + 1),
+				|location, index| {
+					let found = tree.get(index, &positions, location, f32::MAX);
+					match found.last() {
+						Some(furthest) if found.len() > neighbors => {
+							furthest.distance.sqrt() - edge(positions[index], key)
+						},
+						_ => MAX_STATISTICAL_HALO,
+					}
+				},
+			)
+			.reduce(|| 0.0, f32::max);
+		halos.insert(key, halo.clamp(0.0, MAX_STATISTICAL_HALO));
+		let batch = cache.new_entry();
+		cache.add_chunk(&batch, points)?;
+		batches.push(batch);
+	}
+
+	let max_halo = halos.values().copied().fold(0.0, f32::max);
+	let tiles = split(
+		batches,
+		|key| halos.get(&key).copied().unwrap_or_default(),
+		max_halo,
+		cache,
+	)?;
+
+	// the mean distances and their mean and variance over all points
+	let (mut count, mut sum, mut squares) = (0usize, 0.0f64, 0.0f64);
+	let mut filtered = Vec::with_capacity(tiles.len());
+	for tile in tiles.into_values() {
+		let (points, positions) = read(tile, cache)?;
+		if points.is_empty() {
+			continue;
+		}
+		let tree = NeighborsTree::new(&positions);
+		let mean_distances = (0..points.len())
+			.into_par_iter()
+			.map_init(
+				|| bytemuck::zeroed_vec(neighbors + 1),
+				|location, index| {
+					let neighbors = tree.get(index, &positions, location, f32::MAX);
+					if neighbors.len() > 1 {
+						neighbors[1..]
+							.iter()
+							.map(|entry| entry.distance.sqrt())
+							.sum::<f32>() / (neighbors.len() - 1) as f32
+					} else {
+						0.0
+					}
+				},
+			)
+			.collect::<Vec<_>>();
+		drop(tree);
+		progress.step_by(points.len());
+
+		for &mean in &mean_distances {
+			count += 1;
+			sum += mean as f64;
+			squares += mean as f64 * mean as f64;
+		}
+		let batch = cache.new_entry();
+		let means = cache.new_entry();
+		cache.add_chunk(&batch, points)?;
+		cache.add_chunk(&means, mean_distances)?;
+		filtered.push((batch, means));
+	}
+	if count == 0 {
+		return Ok(Vec::new());
+	}
+
+	let mean = sum / count as f64;
+	let variance = (squares / count as f64 - mean * mean).max(0.0);
+	let max_mean_distance = (mean + settings.statistical_outlier_deviations as f64 * variance.sqrt()) as f32;
+
+	*kept = (Vector::new([f32::MAX; 3]), Vector::new([f32::MIN; 3]));
+	let mut batches = Vec::with_capacity(filtered.len());
+	for (batch, means) in filtered {
+		let mut points = cache.read(batch).read()?;
+		let mut means = cache.read(means).read()?.into_iter();
+		points.retain(|point| {
+			let keep = means.next().unwrap() <= max_mean_distance;
+			if keep {
+				insert(kept, point);
+			} else {
+				statistics.statistical_outliers += 1;
+			}
+			keep
+		});
+		if points.is_empty() {
+			continue;
+		}
+		let batch = cache.new_entry();
+		cache.add_chunk(&batch, points)?;
+		batches.push(batch);
+	}
+	Ok(batches)
+}
+
+/// Split the points into tiles, points near a neighboring tile are also added to its halo.
+fn split(
+	batches: Vec<CacheIndex<SourcePoint>>,
+	halo: impl Fn([i32; 2]) -> f32,
+	max_halo: f32,
+	cache: &mut Cache,
+) -> Result<BTreeMap<[i32; 2], Tile>, Error> {
+	let reach = (max_halo / TILE_SIZE).ceil() as i32;
+	let mut tiles = BTreeMap::<[i32; 2], Tile>::new();
+	for batch in batches {
+		for point in cache.read(batch).read()? {
+			let own = key(point.position);
+			for dx in -reach..=reach {
+				for dz in -reach..=reach {
+					let key = [own[0] + dx, own[1] + dz];
+					if key != own && distance(point.position, key) > halo(key) {
+						continue;
+					}
+					let tile = tiles.entry(key).or_insert_with(|| Tile {
+						points: cache.new_entry(),
+						halo: cache.new_entry(),
+					});
+					if key == own {
+						cache.add_value(&tile.points, point)?;
+					} else {
+						cache.add_value(&tile.halo, point)?;
+					}
+				}
+			}
+		}
+	}
+	Ok(tiles)
+}
+
+/// Points of the tile and the positions of the points and the halo.
+fn read(tile: Tile, cache: &mut Cache) -> Result<(Vec<SourcePoint>, Vec<Vector<3, f32>>), Error> {
+	let points = cache.read(tile.points).read()?;
+	let halo = cache.read(tile.halo).read()?;
+	let positions = points
+		.iter()
+		.chain(&halo)
+		.map(|point| point.position)
+		.collect();
+	Ok((points, positions))
+}
+
+fn insert((min, max): &mut Bounds, point: &SourcePoint) {
+	*min = min.min(point.position);
+	*max = max.max(point.position);
+}
+
+/// Tile of the horizontal position.
+fn key(position: Vector<3, f32>) -> [i32; 2] {
+	[
+		(position[X] / TILE_SIZE).floor() as i32,
+		(position[Z] / TILE_SIZE).floor() as i32,
+	]
+}
+
+/// Horizontal distance between the position and the tile.
+fn distance(position: Vector<3, f32>, key: [i32; 2]) -> f32 {
+	let axis = |value: f32, tile: i32| {
+		let start = tile as f32 * TILE_SIZE;
+		(start - value).max(value - start - TILE_SIZE).max(0.0)
+	};
+	let (x, z) = (axis(position[X], key[0]), axis(position[Z], key[1]));
+	(x * x + z * z).sqrt()
+}
+
+/// Horizontal distance from the position inside the tile to the nearest tile edge.
+fn edge(position: Vector<3, f32>, key: [i32; 2]) -> f32 {
+	let axis = |value: f32, tile: i32| {
+		let start = tile as f32 * TILE_SIZE;
+		(value - start).min(start + TILE_SIZE - value)
+	};
+	axis(position[X], key[0])
+		.min(axis(position[Z], key[1]))
+		.max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+	use math::Y;
+
+	use super::*;
+	use crate::progress::Silent;
+
+	/// A square ring of points 2.5 meters apart around the corner of four tiles, the two nearest
+	/// neighbors of every point are its neighbors on the ring.
+	fn ring() -> Vec<Vector<3, f32>> {
+		let mut positions = Vec::new();
+		for index in 0..7 {
+			let along = 1.25 + index as f32 * 2.5;
+			positions.push(Vector::new([along, 0.0, 1.25]));
+			positions.push(Vector::new([18.75, 0.0, along]));
+			positions.push(Vector::new([20.0 - along, 0.0, 18.75]));
+			positions.push(Vector::new([1.25, 0.0, 20.0 - along]));
+		}
+		positions
+	}
+
+	/// Remaining points and bounds after the statistical filter with two neighbors.
+	fn filter(positions: &[Vector<3, f32>], statistics: &mut Statistics) -> (usize, Vector<3, f32>) {
+		let mut cache = Cache::new(usize::MAX, None);
+		let batch = cache.new_entry();
+		let points = positions
+			.iter()
+			.map(|&position| SourcePoint { position, attributes: Default::default() })
+			.collect();
+		cache.add_chunk(&batch, points).unwrap();
+
+		let settings = Settings {
+			statistical_outlier_filter: true,
+			statistical_outlier_neighbors: 2,
+			statistical_outlier_deviations: 1.0,
+			..Settings::default()
+		};
+		let (mut min, mut max) = (Vector::new([0.0; 3]), Vector::new([20.0; 3]));
+		let batches = denoise(
+			vec![batch],
+			(&mut min, &mut max),
+			&settings,
+			&mut cache,
+			statistics,
+			&Silent,
+		)
+		.unwrap();
+		let remaining = batches
+			.into_iter()
+			.map(|batch| cache.read(batch).read().unwrap().len())
+			.sum();
+		(remaining, max)
+	}
+
+	#[test]
+	fn statistical_filter_across_tile_edges() {
+		// the neighbors on the ring are 1.25 meters behind the tile edges
+		let mut statistics = Statistics::default();
+		let (remaining, _) = filter(&ring(), &mut statistics);
+		assert_eq!(statistics.statistical_outliers, 0);
+		assert_eq!(remaining, 28);
+	}
+
+	#[test]
+	fn statistical_outlier_above_ring() {
+		let mut positions = ring();
+		positions.push(Vector::new([10.0, 8.0, 10.0]));
+		let mut statistics = Statistics::default();
+		let (remaining, max) = filter(&positions, &mut statistics);
+		assert_eq!(statistics.statistical_outliers, 1);
+		assert_eq!(remaining, 28);
+		assert_eq!(max[Y], 0.0);
+	}
+}
@@ -0,0 +1,177 @@
+use std::collections::{BTreeMap, HashMap};
+
+use math::{Vector, X, Y, Z};
+use rayon::prelude::*;
+
+use crate::{
+	cache::{Cache, CacheIndex},
+	ordered,
+	progress::{Observer, Progress},
+	source::SourcePoint,
+	Error, Settings, Statistics,
+};
+
+/// Neighbor cells for the minimum spacing, the cells are as large as the spacing.
+const NEIGHBORS: [[i32; 3]; 27] = {
+	let mut neighbors = [[0; 3]; 27];
+	let mut index = 0;
+	while index < 27 {
+		neighbors[index] = [
+			(index % 3) as i32 - 1,
+			(index / 3 % 3) as i32 - 1,
+			(index / 9) as i32 - 1,
+		];
+		index += 1;
+	}
+	neighbors
+};
+
+/// Horizontal size of the tiles in meters, rounded up to whole cells.
+const TILE_SIZE: f32 = 10.0;
+
+/// Reduce the point density of the read points.
+///
+/// The points are collected in tiles aligned to the voxels, which are reduced in parallel with
+/// their own voxels and spacing cells, so the memory depends on the tile size instead of the input.
+/// The minimum spacing is only kept inside a tile, points of neighboring tiles can be nearer.
+pub struct Downsample {
+	voxel_size: Option<f32>,
+	min_spacing: Option<f32>,
+	cell_size: f32,
+	tile_cells: i32,
+	tiles: BTreeMap<[i32; 2], CacheIndex<SourcePoint>>,
+	length: usize,
+}
+
+impl Downsample {
+	pub fn new(settings: &Settings) -> Option<Self> {
+		let cell_size = settings.voxel_size.or(settings.min_spacing)?;
+		Some(Self {
+			voxel_size: settings.voxel_size,
+			min_spacing: settings.min_spacing,
+			cell_size,
+			tile_cells: (TILE_SIZE / cell_size).ceil().max(1.0) as i32,
+			tiles: BTreeMap::new(),
+			length: 0,
+		})
+	}
+
+	pub fn add_point(&mut self, point: SourcePoint, cache: &mut Cache) -> Result<(), Error> {
+		let cell = key(point.position, self.cell_size);
+		let tile = [
+			cell[0].div_euclid(self.tile_cells),
+			cell[2].div_euclid(self.tile_cells),
+		];
+		let tile = self.tiles.entry(tile).or_insert_with(|| cache.new_entry());
+		cache.add_value(tile, point)?;
+		self.length += 1;
+		Ok(())
+	}
+
+	/// Reduce the tiles in parallel. Returns the kept points of every tile ordered by the tile position.
+	pub fn reduce(
+		self,
+		cache: &mut Cache,
+		statistics: &mut Statistics,
+		observer: &dyn Observer,
+	) -> Result<Vec<CacheIndex<SourcePoint>>, Error> {
+		let mut progress = Progress::new("Downsample", self.length, observer);
+		let mut batches = Vec::with_capacity(self.tiles.len());
+		let mut tiles = self.tiles.into_values();
+		loop {
+			let window = tiles
+				.by_ref()
+				.take(ordered::WINDOW)
+				.map(|tile| cache.read(tile))
+				.collect::<Vec<_>>();
+			if window.is_empty() {
+				break;
+			}
+			let reduced = window
+				.into_par_iter()
+				.map(|tile| {
+					let mut points = tile.read()?;
+					let length = points.len();
+					if let Some(size) = self.voxel_size {
+						points = voxel(points, size);
+					}
+					if let Some(spacing) = self.min_spacing {
+						points = self::spacing(points, spacing);
+					}
+					Ok((length, points))
+				})
+				.collect::<Result<Vec<_>, Error>>()?;
+			for (length, points) in reduced {
+				progress.step_by(length);
+				if points.is_empty() {
+					continue;
+				}
+				statistics.downsampled_points += points.len();
+				let batch = cache.new_entry();
+				cache.add_chunk(&batch, points)?;
+				batches.push(batch);
+			}
+		}
+		statistics.times.downsample = progress.finish();
+		Ok(batches)
+	}
+}
+
+/// Keep the point nearest to the voxel center for every voxel.
+fn voxel(points: Vec<SourcePoint>, size: f32) -> Vec<SourcePoint> {
+	let mut nearest = HashMap::<[i32; 3], (f32, usize)>::new();
+	for (index, point) in points.iter().enumerate() {
+		let key = key(point.position, size);
+		let center = Vector::new(key.map(|x| (x as f32 + 0.5) * size));
+		let distance = (point.position - center).length_squared();
+		match nearest.get_mut(&key) {
+			Some(entry) if entry.0 <= distance => {},
+			Some(entry) => *entry = (distance, index),
+			None => _ = nearest.insert(key, (distance, index)),
+		}
+	}
+
+	// keep the order of the points, the map order changes between runs
+	let mut keep = vec![false; points.len()];
+	for (_, index) in nearest.into_values() {
+		keep[index] = true;
+	}
+	points
+		.into_iter()
+		.zip(keep)
+		.filter_map(|(point, keep)| keep.then_some(point))
+		.collect()
+}
+
+/// Keep points without a kept point in the spacing distance.
+fn spacing(points: Vec<SourcePoint>, spacing: f32) -> Vec<SourcePoint> {
+	let mut cells = HashMap::new();
+	points
+		.into_iter()
+		.filter(|point| insert(&mut cells, point.position, spacing))
+		.collect()
+}
+
+fn key(position: Vector<3, f32>, size: f32) -> [i32; 3] {
+	[X, Y, Z].map(|dimension| (position[dimension] / size).floor() as i32)
+}
+
+/// Insert the position if no other position is nearer than the spacing.
+fn insert(cells: &mut HashMap<[i32; 3], Vec<Vector<3, f32>>>, position: Vector<3, f32>, spacing: f32) -> bool {
+	let key = key(position, spacing);
+	let spacing_squared = spacing * spacing;
+	for offset in NEIGHBORS {
+		let neighbor = [key[0] + offset[0], key[1] + offset[1], key[2] + offset[2]];
+		let Some(cell) = cells.get(&neighbor) else {
+			continue;
+		};
+		if cell
+			.iter()
+			.any(|&other| (other - position).length_squared() < spacing_squared)
+		{
+			return false;
+		}
+	}
+	cells.entry(key).or_default().push(position);
+	true
+}
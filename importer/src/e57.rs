@@ -0,0 +1,200 @@
+use e57::{CartesianCoordinate, E57Reader, PointCloud, RecordName, Transform};
+use math::Vector;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::{
+	attributes::{Attribute, Attributes},
+	source::{to_importer, Bounds, Chunk, Source, SourcePoint, CHUNK_SIZE},
+	Error,
+};
+
+/// All scans in an e57 file, positioned with their pose.
+pub struct E57 {
+	total: usize,
+	bounds: Bounds,
+	crs: Option<String>,
+	attributes: Vec<Attribute>,
+
+	path: PathBuf,
+}
+
+impl E57 {
+	pub fn new(path: &Path) -> Result<Self, Error> {
+		let mut reader = E57Reader::from_file(path)?;
+		let crs = reader
+			.coordinate_metadata()
+			.filter(|crs| !crs.is_empty())
+			.map(String::from);
+
+		let mut bounds = Bounds::new();
+		let mut total = 0;
+		let mut attributes = Vec::new();
+		for pointcloud in reader.pointclouds() {
+			if pointcloud.has_intensity() && !attributes.contains(&Attribute::Intensity) {
+				attributes.push(Attribute::Intensity);
+			}
+			if pointcloud.has_color() && !attributes.contains(&Attribute::Color) {
+				attributes.push(Attribute::Color);
+			}
+			if let Some(scan) = header_bounds(&pointcloud) {
+				bounds.merge(scan);
+				total += pointcloud.records as usize;
+				continue;
+			}
+			for point in reader.pointcloud_simple(&pointcloud)? {
+				if let CartesianCoordinate::Valid { x, y, z } = point?.cartesian {
+					bounds.insert(to_importer(Vector::new([x, y, z])));
+					total += 1;
+				}
+			}
+		}
+		if bounds.is_empty() {
+			bounds.insert(Vector::default());
+		}
+
+		Ok(Self {
+			total,
+			bounds,
+			crs,
+			attributes,
+			path: path.to_owned(),
+		})
+	}
+}
+
+impl Source for E57 {
+	fn bounds(&self) -> Bounds {
+		self.bounds
+	}
+
+	fn total(&self) -> usize {
+		self.total
+	}
+
+	fn attributes(&self) -> Vec<Attribute> {
+		self.attributes.clone()
+	}
+
+	fn crs(&self) -> Option<String> {
+		self.crs.clone()
+	}
+
+	fn read(self: Box<Self>, center: Vector<3, f64>, cb: &(dyn Fn(Chunk) + Sync)) -> Result<(), Error> {
+		let pointclouds = E57Reader::from_file(&self.path)?.pointclouds();
+
+		// the points of a single scan can only be read sequential, so the next chunk of multiple
+		// scans is read in parallel and the chunks are passed on in the order of the scans
+		for pointclouds in pointclouds.chunks(rayon::current_num_threads()) {
+			let mut readers = pointclouds
+				.iter()
+				.map(|_| E57Reader::from_file(&self.path))
+				.collect::<Result<Vec<_>, _>>()?;
+			let mut scans = readers
+				.iter_mut()
+				.zip(pointclouds)
+				.map(|(reader, pointcloud)| reader.pointcloud_simple(pointcloud))
+				.collect::<Result<Vec<_>, _>>()?;
+			while !scans.is_empty() {
+				let chunks = scans
+					.par_iter_mut()
+					.map(|scan| read_chunk(scan, center))
+					.collect::<Result<Vec<_>, Error>>()?;
+				// finished scans return an empty chunk
+				scans = scans
+					.into_iter()
+					.zip(&chunks)
+					.filter(|(_, chunk)| !chunk.is_empty())
+					.map(|(scan, _)| scan)
+					.collect();
+				for chunk in chunks {
+					if !chunk.is_empty() {
+						cb(Chunk::from_points(chunk));
+					}
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Read the next valid points of a scan, empty if the scan is finished.
+fn read_chunk(
+	scan: &mut impl Iterator<Item = Result<e57::Point, e57::Error>>,
+	center: Vector<3, f64>,
+) -> Result<Vec<SourcePoint>, Error> {
+	// colors and intensities are normalized between 0 and 1
+	let value = |value: f32| (value.clamp(0.0, 1.0) * u16::MAX as f32) as u16;
+	let mut points = Vec::with_capacity(CHUNK_SIZE);
+	for point in scan {
+		let point = point?;
+		let CartesianCoordinate::Valid { x, y, z } = point.cartesian else {
+			continue;
+		};
+		let mut attributes = Attributes::default();
+		if let Some(color) = point.color {
+			attributes.color = [value(color.red), value(color.green), value(color.blue)];
+		}
+		if let Some(intensity) = point.intensity {
+			attributes.intensity = value(intensity);
+		}
+		points.push(SourcePoint {
+			position: (to_importer(Vector::new([x, y, z])) - center).map(|x| x as f32),
+			attributes,
+		});
+		if points.len() >= CHUNK_SIZE {
+			break;
+		}
+	}
+	Ok(points)
+}
+
+/// Bounds of the scan from the header moved by its pose.
+///
+/// `None` without bounds in the header or if some points could be invalid, then the points
+/// have to be read to count them.
+fn header_bounds(pointcloud: &PointCloud) -> Option<Bounds> {
+	let invalid = pointcloud
+		.prototype
+		.iter()
+		.any(|record| record.name == RecordName::CartesianInvalidState);
+	if !pointcloud.has_cartesian() || invalid {
+		return None;
+	}
+	let header = pointcloud.cartesian_bounds.as_ref()?;
+	let min = [header.x_min?, header.y_min?, header.z_min?];
+	let max = [header.x_max?, header.y_max?, header.z_max?];
+
+	let mut bounds = Bounds::new();
+	for corner in 0..8 {
+		let position = [0, 1, 2].map(|axis| {
+			if corner & (1 << axis) == 0 {
+				min[axis]
+			} else {
+				max[axis]
+			}
+		});
+		let position = match &pointcloud.transform {
+			Some(transform) => pose(transform, position),
+			None => position,
+		};
+		bounds.insert(to_importer(Vector::new(position)));
+	}
+	Some(bounds)
+}
+
+/// Rotate by the quaternion and translate the position.
+fn pose(transform: &Transform, [x, y, z]: [f64; 3]) -> [f64; 3] {
+	let q = &transform.rotation;
+	let t = &transform.translation;
+	// v + 2w (q × v) + 2 q × (q × v)
+	let cross = |[a, b, c]: [f64; 3], [d, e, f]: [f64; 3]| [b * f - c * e, c * d - a * f, a * e - b * d];
+	let axis = [q.x, q.y, q.z];
+	let u = cross(axis, [x, y, z]).map(|value| 2.0 * value);
+	let v = cross(axis, u);
+	[
+		x + q.w * u[0] + v[0] + t.x,
+		y + q.w * u[1] + v[1] + t.y,
+		z + q.w * u[2] + v[2] + t.z,
+	]
+}
@@ -0,0 +1,447 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	path::{Path, PathBuf},
+	sync::Mutex,
+};
+
+use math::{Vector, X, Y};
+use project::{DataFile, IndexData, Project};
+use serde_json::{json, Value};
+
+use crate::{calculations::NeighborsTree, inspect::print_text, source, Error, OutputFormat, TextFormat};
+
+/// Height in meters above the lowest point of a segment used for the stem position.
+const STEM_HEIGHT: f32 = 1.0;
+
+#[derive(clap::Args)]
+pub struct EvaluateCommand {
+	/// Project file (`project.epc`) with the segments
+	project: PathBuf,
+
+	/// Labelled point cloud (`.las` or `.laz`) or stem positions (`.csv` with x and y)
+	reference: PathBuf,
+
+	/// Field with the tree labels in the reference point cloud, points with the label 0 are no tree
+	#[arg(long, default_value = "point-source-id")]
+	reference_label: String,
+
+	/// Maximum distance in meters to the nearest reference point or stem
+	#[arg(long, default_value_t = 1.0)]
+	max_distance: f32,
+
+	/// Minimum intersection over union for a segment to match a labelled tree
+	#[arg(long, default_value_t = 0.5)]
+	min_iou: f32,
+
+	/// Format for the output
+	#[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+	format: OutputFormat,
+}
+
+/// Score the segments of a project against reference trees.
+///
+/// Labelled point clouds are matched by the point overlap, stem positions by the distance to
+/// the stems of the segments.
+pub fn evaluate(command: EvaluateCommand) -> Result<(), Error> {
+	let project = Project::from_file(&command.project)?;
+	let extension = command
+		.reference
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.map(|extension| extension.to_lowercase())
+		.unwrap_or_default();
+	let mut result = match extension.as_str() {
+		"las" | "laz" => points(&command, &project)?,
+		"csv" => stems(&command, &project)?,
+		_ => return Err(Error::UnsupportedFormat(extension)),
+	};
+	result["project"] = json!(command.project.display().to_string());
+	result["reference"] = json!(command.reference.display().to_string());
+
+	match command.format {
+		OutputFormat::Text => print_text(&result, 0),
+		OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&result).unwrap()),
+	}
+	Ok(())
+}
+
+/// Match by the most overlapping points, the reference label for each project point is the
+/// label of the nearest reference point.
+fn points(command: &EvaluateCommand, project: &Project) -> Result<Value, Error> {
+	let georeference = &project.georeference;
+	let center = georeference.axes.to_project(georeference.origin);
+	let source = source::open(
+		std::slice::from_ref(&command.reference),
+		&TextFormat::default(),
+		Some(&command.reference_label),
+	)?;
+	let reference = Mutex::new((Vec::new(), Vec::new()));
+	source.read(center, &|chunk| {
+		let mut reference = reference.lock().unwrap();
+		for point in chunk {
+			reference.0.push(point.position);
+			reference.1.push(point.attributes.label);
+		}
+	})?;
+	let (positions, labels) = reference.into_inner().unwrap();
+
+	let tree = NeighborsTree::new(&positions);
+	let max_distance = command.max_distance * command.max_distance;
+
+	let mut overlap = HashMap::<(u32, u32), usize>::new();
+	let mut segment_sizes = BTreeMap::<u32, usize>::new();
+	let mut reference_sizes = BTreeMap::<u32, usize>::new();
+	for_each_point(&command.project, project, |position, segment| {
+		let label = tree
+			.nearest(position, max_distance)
+			.map(|index| labels[index])
+			.unwrap_or_default();
+		let segment = if project::is_reserved_segment(segment) {
+			0
+		} else {
+			segment
+		};
+		if segment != 0 {
+			*segment_sizes.entry(segment).or_default() += 1;
+		}
+		if label != 0 {
+			*reference_sizes.entry(label).or_default() += 1;
+		}
+		if segment != 0 && label != 0 {
+			*overlap.entry((segment, label)).or_default() += 1;
+		}
+	})?;
+
+	let matches = match_overlap(&overlap, &segment_sizes, &reference_sizes, command.min_iou);
+
+	let trees = reference_sizes
+		.iter()
+		.map(|(&label, &points)| {
+			let (segment, iou) = match matches.get(&label) {
+				Some(&(segment, iou)) => (Some(segment), iou),
+				None => (None, 0.0),
+			};
+			json!({
+				"reference": label,
+				"points": points,
+				"segment": segment,
+				"iou": iou,
+			})
+		})
+		.collect::<Vec<_>>();
+	let mean_iou = matches.values().map(|&(_, iou)| iou).sum::<f32>() / matches.len().max(1) as f32;
+
+	let mut result = scores(
+		"points",
+		reference_sizes.len(),
+		segment_sizes.len(),
+		matches.len(),
+	);
+	result["mean_iou"] = json!(mean_iou);
+	result["trees"] = json!(trees);
+	Ok(result)
+}
+
+/// Match by the nearest stem, the stem of a segment is the mean of its lowest points.
+fn stems(command: &EvaluateCommand, project: &Project) -> Result<Value, Error> {
+	let reference = read_stems(&command.reference)?;
+
+	let mut lowest = HashMap::<u32, f32>::new();
+	for_each_point(&command.project, project, |position, segment| {
+		if !project::is_reserved_segment(segment) {
+			let lowest = lowest.entry(segment).or_insert(f32::MAX);
+			*lowest = lowest.min(position[Y]);
+		}
+	})?;
+	let mut sums = BTreeMap::<u32, (Vector<3, f64>, usize)>::new();
+	for_each_point(&command.project, project, |position, segment| {
+		if project::is_reserved_segment(segment) || position[Y] > lowest[&segment] + STEM_HEIGHT {
+			return;
+		}
+		let sum = sums.entry(segment).or_default();
+		sum.0 += project.georeference.to_source(position);
+		sum.1 += 1;
+	})?;
+	let segments = sums
+		.into_iter()
+		.map(|(segment, (sum, count))| {
+			let stem = sum / count as f64;
+			(segment, [stem[X], stem[Y]])
+		})
+		.collect::<Vec<_>>();
+
+	let matches = match_stems(&reference, &segments, command.max_distance as f64);
+
+	let trees = reference
+		.iter()
+		.enumerate()
+		.map(|(tree, position)| {
+			let (segment, distance) = match matches.get(&tree) {
+				Some(&(segment, distance)) => (Some(segment), Some(distance)),
+				None => (None, None),
+			};
+			json!({
+				"reference": tree + 1,
+				"position": position,
+				"segment": segment,
+				"distance": distance,
+			})
+		})
+		.collect::<Vec<_>>();
+	let mean_distance = matches.values().map(|&(_, distance)| distance).sum::<f64>() / matches.len().max(1) as f64;
+
+	let mut result = scores("stems", reference.len(), segments.len(), matches.len());
+	result["mean_distance"] = json!(mean_distance);
+	result["trees"] = json!(trees);
+	Ok(result)
+}
+
+/// Segment and intersection over union for the labelled trees, the pairs with the largest
+/// intersection over union are matched first.
+fn match_overlap(
+	overlap: &HashMap<(u32, u32), usize>,
+	segment_sizes: &BTreeMap<u32, usize>,
+	reference_sizes: &BTreeMap<u32, usize>,
+	min_iou: f32,
+) -> BTreeMap<u32, (u32, f32)> {
+	let iou = |(segment, label): (u32, u32), overlap: usize| {
+		overlap as f32 / (segment_sizes[&segment] + reference_sizes[&label] - overlap) as f32
+	};
+	let mut candidates = overlap
+		.iter()
+		.map(|(&pair, &overlap)| (pair, iou(pair, overlap)))
+		.filter(|&(_, iou)| iou >= min_iou)
+		.collect::<Vec<_>>();
+	candidates.sort_by(|(a, a_iou), (b, b_iou)| b_iou.total_cmp(a_iou).then(a.cmp(b)));
+	greedy(
+		candidates
+			.into_iter()
+			.map(|((segment, label), iou)| (label, segment, iou)),
+	)
+}
+
+/// Segment and distance for the reference stems, the nearest pairs are matched first.
+fn match_stems(reference: &[[f64; 2]], segments: &[(u32, [f64; 2])], max_distance: f64) -> BTreeMap<usize, (u32, f64)> {
+	let mut candidates = Vec::new();
+	for (tree, position) in reference.iter().enumerate() {
+		for &(segment, stem) in segments {
+			let distance = ((position[0] - stem[0]).powi(2) + (position[1] - stem[1]).powi(2)).sqrt();
+			if distance <= max_distance {
+				candidates.push((distance, tree, segment));
+			}
+		}
+	}
+	candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+	greedy(
+		candidates
+			.into_iter()
+			.map(|(distance, tree, segment)| (tree, segment, distance)),
+	)
+}
+
+/// Match every reference and segment at most once, in the order of the candidates.
+fn greedy<R: Ord, V>(candidates: impl IntoIterator<Item = (R, u32, V)>) -> BTreeMap<R, (u32, V)> {
+	let mut matches = BTreeMap::new();
+	let mut matched_segments = Vec::new();
+	for (reference, segment, value) in candidates {
+		if matches.contains_key(&reference) || matched_segments.contains(&segment) {
+			continue;
+		}
+		matches.insert(reference, (segment, value));
+		matched_segments.push(segment);
+	}
+	matches
+}
+
+/// Detection, omission and commission for the matched trees.
+fn scores(method: &str, references: usize, segments: usize, matched: usize) -> Value {
+	let rate = |count: usize, total: usize| {
+		if total == 0 {
+			0.0
+		} else {
+			count as f32 / total as f32
+		}
+	};
+	let recall = rate(matched, references);
+	let precision = rate(matched, segments);
+	let f_score = if recall + precision > 0.0 {
+		2.0 * recall * precision / (recall + precision)
+	} else {
+		0.0
+	};
+	json!({
+		"method": method,
+		"reference_trees": references,
+		"segments": segments,
+		"matched": matched,
+		"detection_rate": recall,
+		"omission": {
+			"trees": references - matched,
+			"rate": rate(references - matched, references),
+		},
+		"commission": {
+			"segments": segments - matched,
+			"rate": rate(segments - matched, segments),
+		},
+		"precision": precision,
+		"recall": recall,
+		"f_score": f_score,
+	})
+}
+
+/// Call `cb` with the position and segment of every point in the leaves of the project.
+fn for_each_point(path: &Path, project: &Project, mut cb: impl FnMut(Vector<3, f32>, u32)) -> Result<(), Error> {
+	let mut points = DataFile::<project::Point>::open(path.with_file_name("points.data"))?;
+	let mut segments = DataFile::<u32>::open(path.with_file_name("segment.data"))?;
+
+	let mut stack = vec![&project.root];
+	while let Some(node) = stack.pop() {
+		match &node.data {
+			IndexData::Branch { children } => stack.extend(children.iter().flatten()),
+			IndexData::Leaf { .. } => {
+				let index = node.index as usize;
+				for (point, segment) in points.read(index)?.into_iter().zip(segments.read(index)?) {
+					cb(point.position, segment);
+				}
+			},
+		}
+	}
+	Ok(())
+}
+
+/// Stem positions from the first two columns, lines without numbers like a header are skipped.
+fn read_stems(path: &Path) -> Result<Vec<[f64; 2]>, Error> {
+	let content = std::fs::read_to_string(path)?;
+	let stems = content
+		.lines()
+		.filter_map(|line| {
+			let mut columns = line.split(',').map(|column| column.trim().parse::<f64>());
+			match (columns.next(), columns.next()) {
+				(Some(Ok(x)), Some(Ok(y))) => Some([x, y]),
+				_ => None,
+			}
+		})
+		.collect();
+	Ok(stems)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Sizes of the segments and references from the overlapping points.
+	fn sizes(pairs: &[((u32, u32), usize)], extra: &[(u32, usize)]) -> (BTreeMap<u32, usize>, BTreeMap<u32, usize>) {
+		let mut segments = BTreeMap::new();
+		let mut references = BTreeMap::new();
+		for &((segment, label), count) in pairs {
+			*segments.entry(segment).or_default() += count;
+			*references.entry(label).or_default() += count;
+		}
+		for &(label, count) in extra {
+			*references.entry(label).or_default() += count;
+		}
+		(segments, references)
+	}
+
+	#[test]
+	fn overlap_without_matches() {
+		let pairs = [((1, 10), 2), ((2, 10), 2), ((2, 20), 2)];
+		let overlap = pairs.into_iter().collect::<HashMap<_, _>>();
+		let (segments, references) = sizes(&pairs, &[]);
+		// the best pairs only reach an intersection over union of 0.5
+		assert!(match_overlap(&overlap, &segments, &references, 0.6).is_empty());
+		assert!(match_overlap(&HashMap::new(), &BTreeMap::new(), &BTreeMap::new(), 0.0).is_empty());
+	}
+
+	#[test]
+	fn overlap_segment_with_two_references() {
+		// segment 1 covers tree 10 and half of tree 20, segment 2 the other half of tree 20
+		let pairs = [((1, 10), 10), ((1, 20), 5), ((2, 20), 5)];
+		let overlap = pairs.into_iter().collect::<HashMap<_, _>>();
+		let (segments, references) = sizes(&pairs, &[]);
+		let matches = match_overlap(&overlap, &segments, &references, 0.5);
+		assert_eq!(matches.len(), 2);
+		assert_eq!(matches[&10], (1, 10.0 / 15.0));
+		assert_eq!(matches[&20], (2, 0.5));
+
+		// with a higher minimum only the first tree matches and segment 1 is not used twice
+		let matches = match_overlap(&overlap, &segments, &references, 0.6);
+		assert_eq!(matches.len(), 1);
+		assert_eq!(matches[&10].0, 1);
+	}
+
+	#[test]
+	fn overlap_ties() {
+		// both trees have the same intersection over union with segment 1, the lower label wins
+		let pairs = [((1, 10), 4), ((1, 20), 4)];
+		let overlap = pairs.into_iter().collect::<HashMap<_, _>>();
+		let (segments, references) = sizes(&pairs, &[]);
+		let matches = match_overlap(&overlap, &segments, &references, 0.0);
+		assert_eq!(matches.len(), 1);
+		assert_eq!(matches[&10], (1, 0.5));
+
+		// reference points without a segment lower the intersection over union
+		let pairs = [((1, 10), 4)];
+		let overlap = pairs.into_iter().collect::<HashMap<_, _>>();
+		let (segments, references) = sizes(&pairs, &[(10, 4)]);
+		let matches = match_overlap(&overlap, &segments, &references, 0.0);
+		assert_eq!(matches[&10], (1, 0.5));
+	}
+
+	#[test]
+	fn nearest_stems() {
+		let reference = [[0.0, 0.0], [2.0, 0.0], [10.0, 10.0]];
+		let segments = [(1, [0.5, 0.0]), (2, [1.0, 0.0]), (3, [20.0, 20.0])];
+		let matches = match_stems(&reference, &segments, 1.0);
+		// segment 2 is as near to the first tree as to the second, but segment 1 is nearer to the first
+		assert_eq!(matches.len(), 2);
+		assert_eq!(matches[&0], (1, 0.5));
+		assert_eq!(matches[&1], (2, 1.0));
+
+		assert!(match_stems(&reference, &segments, 0.1).is_empty());
+		assert!(match_stems(&[], &segments, 1.0).is_empty());
+	}
+
+	#[test]
+	fn stem_ties() {
+		// segment 1 has the same distance to both trees, the first tree wins
+		let reference = [[-1.0, 0.0], [1.0, 0.0]];
+		let matches = match_stems(&reference, &[(1, [0.0, 0.0])], 1.0);
+		assert_eq!(matches.len(), 1);
+		assert_eq!(matches[&0], (1, 1.0));
+
+		// a tree with the same distance to two segments gets the lower segment
+		let matches = match_stems(&[[0.0, 0.0]], &[(2, [0.0, 1.0]), (1, [1.0, 0.0])], 1.0);
+		assert_eq!(matches[&0], (1, 1.0));
+	}
+
+	#[test]
+	fn detection_scores() {
+		let result = scores("points", 4, 5, 3);
+		assert_eq!(result["detection_rate"], json!(0.75));
+		assert_eq!(result["recall"], json!(0.75));
+		assert_eq!(result["precision"], json!(0.6f32));
+		assert_eq!(result["omission"]["trees"], json!(1));
+		assert_eq!(result["omission"]["rate"], json!(0.25));
+		assert_eq!(result["commission"]["segments"], json!(2));
+		assert_eq!(result["commission"]["rate"], json!(0.4f32));
+		let f_score = result["f_score"].as_f64().unwrap();
+		assert!((f_score - 2.0 / 3.0).abs() < 1e-6);
+	}
+
+	#[test]
+	fn scores_without_matches() {
+		let result = scores("stems", 3, 2, 0);
+		assert_eq!(result["detection_rate"], json!(0.0));
+		assert_eq!(result["precision"], json!(0.0));
+		assert_eq!(result["f_score"], json!(0.0));
+		assert_eq!(result["omission"]["rate"], json!(1.0));
+		assert_eq!(result["commission"]["rate"], json!(1.0));
+
+		// nothing to detect and nothing segmented
+		let result = scores("stems", 0, 0, 0);
+		assert_eq!(result["detection_rate"], json!(0.0));
+		assert_eq!(result["omission"]["rate"], json!(0.0));
+		assert_eq!(result["f_score"], json!(0.0));
+	}
+}
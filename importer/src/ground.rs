@@ -0,0 +1,223 @@
+use std::{
+	io::{BufWriter, Write},
+	path::Path,
+};
+
+use math::{Vector, X, Y, Z};
+
+use crate::{Error, Settings};
+
+/// Upper limit for the height difference allowed by the slope.
+const MAX_THRESHOLD: f32 = 3.0;
+
+/// Lowest point per horizontal cell, collected while importing.
+pub struct GroundGrid {
+	corner: Vector<3, f32>,
+	cell_size: f32,
+	width: usize,
+	depth: usize,
+	cells: Vec<f32>,
+}
+
+impl GroundGrid {
+	pub fn new(min: Vector<3, f32>, max: Vector<3, f32>, cell_size: f32) -> Self {
+		let width = ((max[X] - min[X]) / cell_size) as usize + 1;
+		let depth = ((max[Z] - min[Z]) / cell_size) as usize + 1;
+		Self {
+			corner: min,
+			cell_size,
+			width,
+			depth,
+			cells: vec![f32::MAX; width * depth],
+		}
+	}
+
+	pub fn insert(&mut self, position: Vector<3, f32>) {
+		let x = (((position[X] - self.corner[X]) / self.cell_size) as usize).min(self.width - 1);
+		let z = (((position[Z] - self.corner[Z]) / self.cell_size) as usize).min(self.depth - 1);
+		let cell = &mut self.cells[x + z * self.width];
+		*cell = cell.min(position[Y]);
+	}
+
+	/// Progressive morphological filter on the lowest points.
+	///
+	/// The opening with growing windows removes objects smaller than the window, cells
+	/// higher than the opened surface by more than the slope allows are not ground.
+	/// Empty and removed cells are interpolated from the remaining ground cells.
+	pub fn dtm(self, settings: &Settings) -> Dtm {
+		let (width, depth) = (self.width, self.depth);
+		let mut known = self
+			.cells
+			.iter()
+			.map(|&height| height != f32::MAX)
+			.collect::<Vec<_>>();
+		let mut surface = self.cells.clone();
+		if !fill(&mut surface, &known, width, depth) {
+			surface.fill(self.corner[Y]);
+		}
+
+		let max_radius = (settings.ground_max_window / self.cell_size / 2.0).max(1.0) as usize;
+		let mut radius = 1;
+		let mut previous = 0;
+		while radius <= max_radius {
+			let opened = dilate(&erode(&surface, width, depth, radius), width, depth, radius);
+			let threshold = if previous == 0 {
+				settings.ground_threshold
+			} else {
+				(settings.ground_threshold + settings.ground_slope * 2.0 * (radius - previous) as f32 * self.cell_size)
+					.min(MAX_THRESHOLD)
+			};
+			for index in 0..surface.len() {
+				if surface[index] - opened[index] > threshold {
+					known[index] = false;
+				}
+			}
+			surface = opened;
+			previous = radius;
+			radius *= 2;
+		}
+
+		let mut heights = self
+			.cells
+			.iter()
+			.zip(&known)
+			.map(|(&height, &known)| if known { height } else { f32::MAX })
+			.collect::<Vec<_>>();
+		if !fill(&mut heights, &known, width, depth) {
+			heights = surface;
+		}
+
+		Dtm {
+			corner: Vector::new([self.corner[X], self.corner[Z]]),
+			cell_size: self.cell_size,
+			width,
+			depth,
+			heights,
+		}
+	}
+}
+
+/// Digital terrain model as a regular grid of heights at the cell centers.
+pub struct Dtm {
+	corner: Vector<2, f32>,
+	cell_size: f32,
+	width: usize,
+	depth: usize,
+	heights: Vec<f32>,
+}
+
+impl Dtm {
+	/// Interpolated ground height at the horizontal position.
+	pub fn height(&self, position: Vector<3, f32>) -> f32 {
+		let x = ((position[X] - self.corner[X]) / self.cell_size - 0.5).clamp(0.0, (self.width - 1) as f32);
+		let z = ((position[Z] - self.corner[Y]) / self.cell_size - 0.5).clamp(0.0, (self.depth - 1) as f32);
+		let (x_0, z_0) = (x as usize, z as usize);
+		let (x_1, z_1) = ((x_0 + 1).min(self.width - 1), (z_0 + 1).min(self.depth - 1));
+		let (x_t, z_t) = (x - x_0 as f32, z - z_0 as f32);
+
+		let get = |x: usize, z: usize| self.heights[x + z * self.width];
+		let near = get(x_0, z_0) * (1.0 - x_t) + get(x_1, z_0) * x_t;
+		let far = get(x_0, z_1) * (1.0 - x_t) + get(x_1, z_1) * x_t;
+		near * (1.0 - z_t) + far * z_t
+	}
+
+	/// Height above the ground.
+	pub fn above(&self, position: Vector<3, f32>) -> f32 {
+		position[Y] - self.height(position)
+	}
+
+	/// Save as ESRI ASCII grid in source coordinates.
+	pub fn save(&self, path: &Path, georeference: &project::Georeference) -> Result<(), Error> {
+		// the rows start in the north, which is the smallest z in importer coordinates
+		let lower_left = georeference.to_source(Vector::new([
+			self.corner[X],
+			0.0,
+			self.corner[Y] + self.depth as f32 * self.cell_size,
+		]));
+		let offset = georeference.to_source(Vector::default());
+
+		let mut file = BufWriter::new(std::fs::File::create(path)?);
+		writeln!(file, "ncols {}", self.width)?;
+		writeln!(file, "nrows {}", self.depth)?;
+		writeln!(file, "xllcorner {}", lower_left[X])?;
+		writeln!(file, "yllcorner {}", lower_left[Y])?;
+		writeln!(file, "cellsize {}", self.cell_size)?;
+		for row in self.heights.chunks_exact(self.width) {
+			let row = row
+				.iter()
+				.map(|&height| (height as f64 + offset[Z]).to_string())
+				.collect::<Vec<_>>();
+			writeln!(file, "{}", row.join(" "))?;
+		}
+		Ok(())
+	}
+}
+
+/// Fill the unknown cells with the mean of the known neighbors, repeated until all cells are
+/// known. Returns `false` if no cell is known.
+fn fill(values: &mut [f32], known: &[bool], width: usize, depth: usize) -> bool {
+	if !known.iter().any(|&known| known) {
+		return false;
+	}
+	let mut known = known.to_vec();
+	let mut missing = known.iter().filter(|&&known| !known).count();
+	while missing > 0 {
+		let mut next = known.clone();
+		for z in 0..depth {
+			for x in 0..width {
+				let index = x + z * width;
+				if known[index] {
+					continue;
+				}
+				let mut sum = 0.0;
+				let mut count = 0;
+				for n_z in z.saturating_sub(1)..(z + 2).min(depth) {
+					for n_x in x.saturating_sub(1)..(x + 2).min(width) {
+						if known[n_x + n_z * width] {
+							sum += values[n_x + n_z * width];
+							count += 1;
+						}
+					}
+				}
+				if count > 0 {
+					values[index] = sum / count as f32;
+					next[index] = true;
+					missing -= 1;
+				}
+			}
+		}
+		known = next;
+	}
+	true
+}
+
+fn erode(values: &[f32], width: usize, depth: usize, radius: usize) -> Vec<f32> {
+	window(values, width, depth, radius, f32::min)
+}
+
+pub fn dilate(values: &[f32], width: usize, depth: usize, radius: usize) -> Vec<f32> {
+	window(values, width, depth, radius, f32::max)
+}
+
+/// Combine all values in the square window, separated into rows and columns.
+fn window(values: &[f32], width: usize, depth: usize, radius: usize, combine: fn(f32, f32) -> f32) -> Vec<f32> {
+	let mut rows = vec![0.0; values.len()];
+	for z in 0..depth {
+		for x in 0..width {
+			rows[x + z * width] = (x.saturating_sub(radius)..(x + radius + 1).min(width))
+				.map(|n_x| values[n_x + z * width])
+				.reduce(combine)
+				.unwrap();
+		}
+	}
+	let mut res = vec![0.0; values.len()];
+	for z in 0..depth {
+		for x in 0..width {
+			res[x + z * width] = (z.saturating_sub(radius)..(z + radius + 1).min(depth))
+				.map(|n_z| rows[x + n_z * width])
+				.reduce(combine)
+				.unwrap();
+		}
+	}
+	res
+}
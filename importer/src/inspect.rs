@@ -0,0 +1,236 @@
+use std::{
+	io::{Seek, SeekFrom},
+	path::{Path, PathBuf},
+};
+
+use laz::{las::file::read_vlrs_and_get_laszip_vlr, laszip::ChunkTable};
+use serde_json::{json, Value};
+
+use crate::{
+	laz::{Format, Header},
+	Error,
+};
+
+#[derive(clap::Args)]
+pub struct InspectCommand {
+	/// Input file (`.las` or `.laz`) or project file (`project.epc`)
+	path: PathBuf,
+
+	/// Format for the output
+	#[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+	format: OutputFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+	/// Indented text
+	Text,
+	/// Single JSON object
+	Json,
+}
+
+/// Print information about an input file or a project.
+pub fn inspect(command: InspectCommand) -> Result<(), Error> {
+	let extension = command
+		.path
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.map(|extension| extension.to_lowercase())
+		.unwrap_or_default();
+	let information = match extension.as_str() {
+		"las" | "laz" => las(&command.path)?,
+		"epc" => project(&command.path)?,
+		_ => return Err(Error::UnsupportedFormat(extension)),
+	};
+
+	match command.format {
+		OutputFormat::Text => print_text(&information, 0),
+		OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&information).unwrap()),
+	}
+	Ok(())
+}
+
+fn las(path: &Path) -> Result<Value, Error> {
+	let mut file = std::fs::File::open(path)?;
+	let header = Header::new(&mut file)?;
+	let records = header.records(&mut file)?;
+	let crs = header.crs(&mut file)?;
+	let format = Format::new(&header)?;
+
+	let chunk_table = if header.is_compressed() {
+		file.seek(SeekFrom::Start(header.header_size as u64))?;
+		let vlr = read_vlrs_and_get_laszip_vlr(&mut file, &header.quick_header()).ok_or(Error::CorruptFile)?;
+		let chunks = ChunkTable::read_from(&mut file, &vlr)?;
+		json!({
+			"chunks": chunks.len(),
+			"chunk_size": vlr.chunk_size(),
+		})
+	} else {
+		Value::Null
+	};
+
+	// copy the fields, because the header is packed
+	let Header {
+		version_major,
+		version_minor,
+		system_identifier,
+		generating_software,
+		creation_day,
+		creation_year,
+		header_size,
+		offset_to_point_data,
+		point_data_record_length,
+		legacy_point_amount_return,
+		number_of_point_records,
+		point_amount_return,
+		..
+	} = header;
+
+	// fields after the point count by return only exist since 1.4
+	let mut points_by_return = if version_minor >= 4 {
+		point_amount_return.to_vec()
+	} else {
+		legacy_point_amount_return
+			.map(|amount| amount as u64)
+			.to_vec()
+	};
+	while points_by_return.last() == Some(&0) {
+		points_by_return.pop();
+	}
+
+	let text = |bytes: &[u8]| {
+		String::from_utf8_lossy(bytes)
+			.trim_end_matches('\0')
+			.trim()
+			.to_owned()
+	};
+	let min = [header.min_x, header.min_y, header.min_z];
+	let max = [header.max_x, header.max_y, header.max_z];
+
+	Ok(json!({
+		"file": path.display().to_string(),
+		"version": format!("{}.{}", version_major, version_minor),
+		"system_identifier": text(&system_identifier),
+		"generating_software": text(&generating_software),
+		"creation": format!("{}-{:03}", creation_year, creation_day),
+		"header_size": header_size,
+		"offset_to_point_data": offset_to_point_data,
+		"point_format": header.point_format(),
+		"point_length": point_data_record_length,
+		"compressed": header.is_compressed(),
+		"points": number_of_point_records,
+		"points_by_return": points_by_return,
+		"attributes": format
+			.attributes()
+			.into_iter()
+			.map(|attribute| attribute.property().1)
+			.collect::<Vec<_>>(),
+		"scale": header.scale().data(),
+		"offset": header.offset().data(),
+		"bounds": {
+			"min": min,
+			"max": max,
+		},
+		"crs": crs,
+		"records": records
+			.iter()
+			.map(|record| json!({
+				"user_id": record.user_id,
+				"record_id": record.record_id,
+				"description": record.description,
+				"length": record.data.len(),
+				"extended": record.extended,
+			}))
+			.collect::<Vec<_>>(),
+		"chunk_table": chunk_table,
+	}))
+}
+
+fn project(path: &Path) -> Result<Value, Error> {
+	let project = project::Project::from_file(path)?;
+
+	fn count(node: &project::IndexNode, nodes: &mut usize, leaves: &mut usize) {
+		*nodes += 1;
+		match &node.data {
+			project::IndexData::Branch { children } => {
+				for child in children.iter().flatten() {
+					count(child, nodes, leaves);
+				}
+			},
+			project::IndexData::Leaf { .. } => *leaves += 1,
+		}
+	}
+	let (mut nodes, mut leaves) = (0, 0);
+	count(&project.root, &mut nodes, &mut leaves);
+
+	let segments = project
+		.properties
+		.iter()
+		.find(|(name, _, _)| name == "segment")
+		.map(|&(_, _, segments)| segments);
+
+	let statistics = std::fs::read_to_string(path.with_file_name("statistics.json"))
+		.ok()
+		.and_then(|content| serde_json::from_str::<Value>(&content).ok());
+
+	Ok(json!({
+		"file": path.display().to_string(),
+		"name": project.name,
+		"depth": project.depth,
+		"nodes": nodes,
+		"leaves": leaves,
+		"properties": project
+			.properties
+			.iter()
+			.map(|(name, display, max)| json!({
+				"name": name,
+				"display": display,
+				"max": max,
+			}))
+			.collect::<Vec<_>>(),
+		"segments": segments,
+		"segment_information": project.segment_information,
+		"georeference": {
+			"origin": project.georeference.origin.data(),
+			"crs": project.georeference.crs,
+		},
+		"statistics": statistics,
+	}))
+}
+
+/// Print objects as indented `key: value` lines, objects in lists use a single line.
+pub fn print_text(value: &Value, indent: usize) {
+	let Value::Object(map) = value else {
+		println!("{}{}", "  ".repeat(indent), inline(value));
+		return;
+	};
+	for (key, value) in map {
+		match value {
+			Value::Object(_) => {
+				println!("{}{}:", "  ".repeat(indent), key);
+				print_text(value, indent + 1);
+			},
+			Value::Array(items) if items.iter().any(|item| item.is_object()) => {
+				println!("{}{}:", "  ".repeat(indent), key);
+				for item in items {
+					println!("{}- {}", "  ".repeat(indent + 1), inline(item));
+				}
+			},
+			_ => println!("{}{}: {}", "  ".repeat(indent), key, inline(value)),
+		}
+	}
+}
+
+fn inline(value: &Value) -> String {
+	match value {
+		Value::Null => String::from("-"),
+		Value::String(value) => value.clone(),
+		Value::Array(items) => items.iter().map(inline).collect::<Vec<_>>().join(", "),
+		Value::Object(map) => map
+			.iter()
+			.map(|(key, value)| format!("{}: {}", key, inline(value)))
+			.collect::<Vec<_>>()
+			.join(", "),
+		value => value.to_string(),
+	}
+}
@@ -0,0 +1,83 @@
+use std::{collections::BTreeMap, num::NonZeroU32};
+
+use math::{Vector, X, Z};
+
+use crate::{
+	cache::{Cache, CacheIndex},
+	progress::{Observer, Progress},
+	segment::{remove_small, Segment, Segmenter, Tiles},
+	source::SourcePoint,
+	Error, Settings, Statistics,
+};
+
+/// Segments from the tree labels in the source instead of segmenting.
+///
+/// Points with the label `0` are unassigned. The stem is at the lowest point of the label.
+pub struct LabelSegmenter {
+	labels: BTreeMap<u32, Label>,
+	length: usize,
+	min_segment_size: usize,
+}
+
+struct Label {
+	points: CacheIndex<SourcePoint>,
+	stem: Vector<2, f32>,
+	lowest: f32,
+}
+
+impl LabelSegmenter {
+	pub fn new(settings: &Settings) -> Self {
+		Self {
+			labels: BTreeMap::new(),
+			length: 0,
+			min_segment_size: settings.min_segment_size,
+		}
+	}
+}
+
+impl Segmenter for LabelSegmenter {
+	fn add_point(&mut self, point: SourcePoint, height: f32, cache: &mut Cache) -> Result<(), Error> {
+		let label = self
+			.labels
+			.entry(point.attributes.label)
+			.or_insert_with(|| Label {
+				points: cache.new_entry(),
+				stem: Vector::default(),
+				lowest: f32::MAX,
+			});
+		if height < label.lowest {
+			label.lowest = height;
+			label.stem = Vector::new([point.position[X], point.position[Z]]);
+		}
+		cache.add_value(&label.points, point)?;
+		self.length += 1;
+		Ok(())
+	}
+
+	fn segments(
+		self: Box<Self>,
+		unassigned: &mut Tiles,
+		statistics: &mut Statistics,
+		cache: &mut Cache,
+		observer: &dyn Observer,
+	) -> Result<Vec<Segment>, Error> {
+		let mut progress = Progress::new("Segmenting", self.length, observer);
+
+		let mut segments = Vec::with_capacity(self.labels.len());
+		for (label, Label { points, stem, .. }) in self.labels {
+			let points = cache.read(points);
+			progress.step_by(points.length());
+			match NonZeroU32::new(label) {
+				Some(label) => segments.push(Segment::new(points, stem).with_label(label)),
+				None => {
+					for point in points.read()? {
+						unassigned.add_point(point, cache)?;
+					}
+				},
+			}
+		}
+
+		statistics.times.segment = progress.finish();
+		remove_small(segments, self.min_segment_size, unassigned, cache)
+	}
+}
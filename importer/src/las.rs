@@ -0,0 +1,105 @@
+use math::Vector;
+use std::{
+	io::{BufReader, Read, Seek, SeekFrom},
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	attributes::Attribute,
+	laz::{Format, Header},
+	ordered,
+	source::{Bounds, Chunk, Source, CHUNK_SIZE},
+	Error,
+};
+
+/// Uncompressed point records.
+pub struct Las {
+	total: usize,
+	chunks: Vec<(u64, usize)>,
+	format: Format,
+	bounds: Bounds,
+	crs: Option<String>,
+
+	path: PathBuf,
+}
+
+impl Las {
+	pub fn new(path: &Path, label: Option<&str>) -> Result<Self, Error> {
+		let mut file = std::fs::File::open(path)?;
+
+		let header = Header::new(&mut file)?;
+		let crs = header.crs(&mut file)?;
+		if header.is_compressed() {
+			return Err(Error::CorruptFile);
+		}
+
+		let total = header.number_of_point_records as usize;
+		let bounds = header.bounds();
+		let mut format = Format::new(&header)?;
+		if let Some(label) = label {
+			format = format.with_label(label, &header.records(&mut file)?)?;
+		}
+		let point_length = format.point_length;
+
+		let start = header.offset_to_point_data as u64;
+		if file.metadata()?.len() < start + (total * point_length) as u64 {
+			return Err(Error::CorruptFile);
+		}
+
+		let chunks = (0..total)
+			.step_by(CHUNK_SIZE)
+			.map(|index| {
+				(
+					start + (index * point_length) as u64,
+					CHUNK_SIZE.min(total - index),
+				)
+			})
+			.collect::<Vec<_>>();
+
+		Ok(Self {
+			chunks,
+			total,
+			format,
+			bounds,
+			crs,
+			path: path.to_owned(),
+		})
+	}
+}
+
+impl Source for Las {
+	fn bounds(&self) -> Bounds {
+		self.bounds
+	}
+
+	fn total(&self) -> usize {
+		self.total
+	}
+
+	fn attributes(&self) -> Vec<Attribute> {
+		self.format.attributes()
+	}
+
+	fn crs(&self) -> Option<String> {
+		self.crs.clone()
+	}
+
+	fn read(self: Box<Self>, center: Vector<3, f64>, cb: &(dyn Fn(Chunk) + Sync)) -> Result<(), Error> {
+		ordered::try_for_each(
+			&self.chunks,
+			|&(s, l)| {
+				let mut file = BufReader::new(std::fs::File::open(&self.path)?);
+				file.seek(SeekFrom::Start(s))?;
+
+				let mut slice = vec![0; l * self.format.point_length];
+				file.read_exact(&mut slice)?;
+
+				Ok(self.format.decode(&slice, center))
+			},
+			|chunk| {
+				cb(chunk);
+				Ok(())
+			},
+		)
+	}
+}
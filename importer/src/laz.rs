@@ -5,50 +5,45 @@ use laz::{
 	LazVlr,
 };
 use math::{Vector, X, Y, Z};
-use rayon::prelude::*;
 use std::{
 	io::{BufReader, Read, Seek, SeekFrom},
 	path::{Path, PathBuf},
 };
 
-use crate::Error;
+use crate::{
+	attributes::{Attribute, Attributes},
+	ordered,
+	source::{to_importer, Bounds, Chunk, Source, SourcePoint},
+	Error,
+};
 
 pub struct Laz {
-	pub total: usize,
+	total: usize,
 	vlr: LazVlr,
 	chunks: Vec<(u64, usize)>,
-	point_length: usize,
-	center: Vector<3, f64>,
-	offset: Vector<3, f64>,
-	scale: Vector<3, f64>,
+	format: Format,
+	bounds: Bounds,
+	crs: Option<String>,
 
 	path: PathBuf,
-
-	pub min: Vector<3, f32>,
-	pub max: Vector<3, f32>,
 }
 
 impl Laz {
-	pub fn new(path: &Path) -> Result<Self, Error> {
+	pub fn new(path: &Path, label: Option<&str>) -> Result<Self, Error> {
 		let mut file = std::fs::File::open(path)?;
 
 		let header = Header::new(&mut file)?;
+		let crs = header.crs(&mut file)?;
+		let mut format = Format::new(&header)?;
+		if let Some(label) = label {
+			format = format.with_label(label, &header.records(&mut file)?)?;
+		}
 
 		file.seek(SeekFrom::Start(header.header_size as u64))?;
 		let vlr = read_vlrs_and_get_laszip_vlr(&mut file, &header.quick_header()).ok_or(Error::CorruptFile)?;
 
 		let total = header.number_of_point_records as usize;
-
-		let point_length = header.point_data_record_length as usize;
-		let scale = Vector::new([
-			header.x_scale_factor,
-			header.y_scale_factor,
-			header.z_scale_factor,
-		]);
-		let offset = Vector::new([header.x_offset, header.y_offset, header.z_offset]);
-		let min = Vector::new([header.min_x, header.min_z, -header.max_y]);
-		let max = Vector::new([header.max_x, header.max_z, -header.min_y]);
-		let center = (min + max) / 2.0;
+		let bounds = header.bounds();
 
 		let chunks = ChunkTable::read_from(&mut file, &vlr)?;
 
@@ -70,136 +65,329 @@ impl Laz {
 			chunks,
 			total,
 			vlr,
-			point_length,
-			scale,
-			offset,
-			center,
+			format,
+			bounds,
+			crs,
 			path: path.to_owned(),
-
-			min: (min - center).map(|x| x as f32),
-			max: (max - center).map(|x| x as f32),
 		})
 	}
+}
 
-	pub fn read(self, cb: impl Fn(Chunk) + std::marker::Sync) -> Result<(), Error> {
-		self.chunks
-			.into_par_iter()
-			.map_init(
-				|| BufReader::new(std::fs::File::open(&self.path).unwrap()),
-				|file, (s, l)| {
-					file.seek(SeekFrom::Start(s))?;
-
-					let mut slice = vec![0; l * self.point_length];
-					match self.vlr.items().first().unwrap().version() {
-						1 | 2 => {
-							let mut decompress = SequentialPointRecordDecompressor::new(file);
-							decompress.set_fields_from(self.vlr.items())?;
-							decompress.decompress_many(&mut slice).unwrap();
-						},
-						3 | 4 => {
-							let mut decompress = LayeredPointRecordDecompressor::new(file);
-							decompress.set_fields_from(self.vlr.items())?;
-							decompress.decompress_many(&mut slice).unwrap();
-						},
-						v => unimplemented!("Laz version {}", v),
-					}
+impl Source for Laz {
+	fn bounds(&self) -> Bounds {
+		self.bounds
+	}
 
-					let chunk = Chunk {
-						slice,
-						current: 0,
-						point_length: self.point_length,
+	fn total(&self) -> usize {
+		self.total
+	}
 
-						offset: self.offset,
-						scale: self.scale,
-						center: self.center,
-					};
+	fn attributes(&self) -> Vec<Attribute> {
+		self.format.attributes()
+	}
 
-					cb(chunk);
-					Ok(())
-				},
-			)
-			.find_any(|r| r.is_err())
-			.unwrap_or(Ok(()))
+	fn crs(&self) -> Option<String> {
+		self.crs.clone()
+	}
+
+	fn read(self: Box<Self>, center: Vector<3, f64>, cb: &(dyn Fn(Chunk) + Sync)) -> Result<(), Error> {
+		ordered::try_for_each(
+			&self.chunks,
+			|&(s, l)| {
+				let mut file = BufReader::new(std::fs::File::open(&self.path)?);
+				file.seek(SeekFrom::Start(s))?;
+
+				let mut slice = vec![0; l * self.format.point_length];
+				match self
+					.vlr
+					.items()
+					.first()
+					.ok_or(Error::CorruptFile)?
+					.version()
+				{
+					1 | 2 => {
+						let mut decompress = SequentialPointRecordDecompressor::new(&mut file);
+						decompress.set_fields_from(self.vlr.items())?;
+						decompress.decompress_many(&mut slice)?;
+					},
+					3 | 4 => {
+						let mut decompress = LayeredPointRecordDecompressor::new(&mut file);
+						decompress.set_fields_from(self.vlr.items())?;
+						decompress.decompress_many(&mut slice)?;
+					},
+					v => return Err(Error::UnsupportedFormat(format!("laz version {}", v))),
+				}
+
+				Ok(self.format.decode(&slice, center))
+			},
+			|chunk| {
+				cb(chunk);
+				Ok(())
+			},
+		)
 	}
 }
 
-pub struct Chunk {
-	slice: Vec<u8>,
-	current: usize,
-	point_length: usize,
+/// Layout of the point records shared by compressed and uncompressed files.
+pub struct Format {
+	pub point_length: usize,
+	point_format: u8,
+	extra_offset: usize,
 	offset: Vector<3, f64>,
 	scale: Vector<3, f64>,
-	center: Vector<3, f64>,
+	label: Option<Label>,
 }
 
-impl Chunk {
-	pub fn length(&self) -> usize {
-		self.slice.len() / self.point_length
-	}
+/// Field with existing tree labels, selected with `--segment-from`.
+#[derive(Clone, Copy, Debug)]
+enum Label {
+	Classification,
+	PointSourceId,
+	/// Field in the extra bytes with the data type from the extra bytes record.
+	Extra {
+		offset: usize,
+		data_type: u8,
+	},
 }
 
-impl Iterator for Chunk {
-	type Item = Vector<3, f32>;
+impl Format {
+	pub fn new(header: &Header) -> Result<Self, Error> {
+		let point_format = header.point_format();
+		let minimum_length = match point_format {
+			0 => 20,
+			1 => 28,
+			2 => 26,
+			3 => 34,
+			4 => 57,
+			5 => 63,
+			6 => 30,
+			7 => 36,
+			8 => 38,
+			9 => 59,
+			10 => 67,
+			v => return Err(Error::UnsupportedFormat(format!("las point format {}", v))),
+		};
+		let point_length = header.point_data_record_length as usize;
+		if point_length < minimum_length {
+			return Err(Error::CorruptFile);
+		}
+
+		Ok(Self {
+			point_length,
+			point_format,
+			extra_offset: minimum_length,
+			offset: header.offset(),
+			scale: header.scale(),
+			label: None,
+		})
+	}
 
-	fn next(&mut self) -> Option<Vector<3, f32>> {
-		if self.current >= self.slice.len() {
-			return None;
+	/// Decode the tree labels from `classification`, `point-source-id` or the named extra bytes field.
+	pub fn with_label(mut self, name: &str, records: &[Record]) -> Result<Self, Error> {
+		let label = match name {
+			"classification" => Label::Classification,
+			"point-source-id" => Label::PointSourceId,
+			name => {
+				// extra bytes records with a 192 byte description per field
+				let mut offset = self.extra_offset;
+				let mut found = None;
+				let descriptions = records
+					.iter()
+					.filter(|record| record.user_id == "LASF_Spec" && record.record_id == 4)
+					.flat_map(|record| record.data.chunks_exact(192));
+				for description in descriptions {
+					let data_type = description[2];
+					let size = extra_size(data_type, description[3]);
+					let field = String::from_utf8_lossy(&description[4..36]);
+					if field.trim_end_matches('\0') == name {
+						if !(1..=10).contains(&data_type) {
+							return Err(Error::UnsupportedFormat(format!(
+								"extra bytes type {} for '{}'",
+								data_type, name
+							)));
+						}
+						if offset + size > self.point_length {
+							return Err(Error::CorruptFile);
+						}
+						found = Some(Label::Extra { offset, data_type });
+						break;
+					}
+					offset += size;
+				}
+				found.ok_or_else(|| Error::MissingLabel(name.into()))?
+			},
+		};
+		self.label = Some(label);
+		Ok(self)
+	}
+
+	/// Attributes stored in the point format.
+	pub fn attributes(&self) -> Vec<Attribute> {
+		let mut attributes = vec![
+			Attribute::Intensity,
+			Attribute::ReturnNumber,
+			Attribute::NumberOfReturns,
+			Attribute::Classification,
+		];
+		if self.color_offset().is_some() {
+			attributes.push(Attribute::Color);
+		}
+		if self.time_offset().is_some() {
+			attributes.push(Attribute::Time);
+		}
+		attributes
+	}
+
+	fn time_offset(&self) -> Option<usize> {
+		match self.point_format {
+			1 | 3 | 4 | 5 => Some(20),
+			6..=10 => Some(22),
+			_ => None,
 		}
-		let slice = &self.slice[self.current..(self.current + 12)];
-		let x = i32::from_le_bytes(slice[0..4].try_into().unwrap());
-		let y = i32::from_le_bytes(slice[4..8].try_into().unwrap());
-		let z = i32::from_le_bytes(slice[8..12].try_into().unwrap());
-		self.current += self.point_length;
-
-		let v = Vector::new([
-			self.offset[X] + x as f64 * self.scale[X],
-			self.offset[Y] + y as f64 * self.scale[Y],
-			self.offset[Z] + z as f64 * self.scale[Z],
-		]);
-		Some((Vector::new([v[X], v[Z], -v[Y]]) - self.center).map(|x| x as f32))
+	}
+
+	fn color_offset(&self) -> Option<usize> {
+		match self.point_format {
+			2 => Some(20),
+			3 | 5 => Some(28),
+			7 | 8 | 10 => Some(30),
+			_ => None,
+		}
+	}
+
+	pub fn decode(&self, slice: &[u8], center: Vector<3, f64>) -> Chunk {
+		let u16_at = |record: &[u8], offset: usize| u16::from_le_bytes([record[offset], record[offset + 1]]);
+		let time_offset = self.time_offset();
+		let color_offset = self.color_offset();
+
+		let points = slice
+			.chunks_exact(self.point_length)
+			.map(|record| {
+				let x = i32::from_le_bytes(record[0..4].try_into().unwrap());
+				let y = i32::from_le_bytes(record[4..8].try_into().unwrap());
+				let z = i32::from_le_bytes(record[8..12].try_into().unwrap());
+
+				let v = Vector::new([
+					self.offset[X] + x as f64 * self.scale[X],
+					self.offset[Y] + y as f64 * self.scale[Y],
+					self.offset[Z] + z as f64 * self.scale[Z],
+				]);
+
+				let mut attributes = Attributes {
+					intensity: u16_at(record, 12),
+					..Default::default()
+				};
+				if self.point_format < 6 {
+					attributes.return_number = record[14] & 0b0000_0111;
+					attributes.number_of_returns = (record[14] >> 3) & 0b0000_0111;
+					attributes.classification = record[15] & 0b0001_1111;
+				} else {
+					attributes.return_number = record[14] & 0b0000_1111;
+					attributes.number_of_returns = record[14] >> 4;
+					attributes.classification = record[16];
+				}
+				if let Some(offset) = time_offset {
+					attributes.time = f64::from_le_bytes(record[offset..(offset + 8)].try_into().unwrap());
+				}
+				if let Some(offset) = color_offset {
+					attributes.color = [
+						u16_at(record, offset),
+						u16_at(record, offset + 2),
+						u16_at(record, offset + 4),
+					];
+				}
+				attributes.label = match self.label {
+					None => 0,
+					Some(Label::Classification) => attributes.classification as u32,
+					Some(Label::PointSourceId) if self.point_format < 6 => u16_at(record, 18) as u32,
+					Some(Label::PointSourceId) => u16_at(record, 20) as u32,
+					Some(Label::Extra { offset, data_type }) => extra_value(&record[offset..], data_type),
+				};
+
+				SourcePoint {
+					position: (to_importer(v) - center).map(|x| x as f32),
+					attributes,
+				}
+			})
+			.collect();
+		Chunk::from_points(points)
 	}
 }
 
+/// Size of an extra bytes field, undocumented fields use the options as size.
+fn extra_size(data_type: u8, options: u8) -> usize {
+	match data_type {
+		0 => options as usize,
+		1..=30 => [1, 1, 2, 2, 4, 4, 8, 8, 4, 8][(data_type as usize - 1) % 10] * ((data_type as usize - 1) / 10 + 1),
+		_ => 0,
+	}
+}
+
+/// Extra bytes value as label, negative values are `0`.
+fn extra_value(bytes: &[u8], data_type: u8) -> u32 {
+	match data_type {
+		1 => bytes[0] as u32,
+		2 => (bytes[0] as i8).max(0) as u32,
+		3 => u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+		4 => i16::from_le_bytes([bytes[0], bytes[1]]).max(0) as u32,
+		5 => u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+		6 => i32::from_le_bytes(bytes[..4].try_into().unwrap()).max(0) as u32,
+		7 => u64::from_le_bytes(bytes[..8].try_into().unwrap()).min(u32::MAX as u64) as u32,
+		8 => i64::from_le_bytes(bytes[..8].try_into().unwrap()).clamp(0, u32::MAX as i64) as u32,
+		9 => f32::from_le_bytes(bytes[..4].try_into().unwrap()).round() as u32,
+		10 => f64::from_le_bytes(bytes[..8].try_into().unwrap()).round() as u32,
+		_ => 0,
+	}
+}
+
+pub struct Record {
+	pub user_id: String,
+	pub record_id: u16,
+	pub description: String,
+	pub extended: bool,
+	pub data: Vec<u8>,
+}
+
 #[repr(C, packed)]
 #[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod, Default, Debug)]
-struct Header {
-	signature: [u8; 4],
-	source_id: u16,
-	global_encoding: u16,
-	guid_1: u32,
-	guid_2: u16,
-	guid_3: u16,
-	guid_4: [u8; 8],
-	version_major: u8,
-	version_minor: u8,
-	system_identifier: [u8; 32],
-	generating_software: [u8; 32],
-	creation_day: u16,
-	creation_year: u16,
-	header_size: u16,
-	offset_to_point_data: u32,
-	number_of_variable_length_records: u32,
-	point_data_record_format: u8,
-	point_data_record_length: u16,
-	legacy_point_amount: u32,
-	legacy_point_amount_return: [u32; 5],
-	x_scale_factor: f64,
-	y_scale_factor: f64,
-	z_scale_factor: f64,
-	x_offset: f64,
-	y_offset: f64,
-	z_offset: f64,
-	max_x: f64,
-	min_x: f64,
-	max_y: f64,
-	min_y: f64,
-	max_z: f64,
-	min_z: f64,
-	waveform_offset: u64,
-	first_vlr_offset: u64,
-	number_of_extended_variable_length_records: u32,
-	number_of_point_records: u64,
-	point_amount_return: [u64; 15],
+pub struct Header {
+	pub signature: [u8; 4],
+	pub source_id: u16,
+	pub global_encoding: u16,
+	pub guid_1: u32,
+	pub guid_2: u16,
+	pub guid_3: u16,
+	pub guid_4: [u8; 8],
+	pub version_major: u8,
+	pub version_minor: u8,
+	pub system_identifier: [u8; 32],
+	pub generating_software: [u8; 32],
+	pub creation_day: u16,
+	pub creation_year: u16,
+	pub header_size: u16,
+	pub offset_to_point_data: u32,
+	pub number_of_variable_length_records: u32,
+	pub point_data_record_format: u8,
+	pub point_data_record_length: u16,
+	pub legacy_point_amount: u32,
+	pub legacy_point_amount_return: [u32; 5],
+	pub x_scale_factor: f64,
+	pub y_scale_factor: f64,
+	pub z_scale_factor: f64,
+	pub x_offset: f64,
+	pub y_offset: f64,
+	pub z_offset: f64,
+	pub max_x: f64,
+	pub min_x: f64,
+	pub max_y: f64,
+	pub min_y: f64,
+	pub max_z: f64,
+	pub min_z: f64,
+	pub waveform_offset: u64,
+	pub first_vlr_offset: u64,
+	pub number_of_extended_variable_length_records: u32,
+	pub number_of_point_records: u64,
+	pub point_amount_return: [u64; 15],
 }
 
 static_assertions::assert_cfg!(target_endian = "little");
@@ -209,7 +397,7 @@ impl Header {
 		let mut header = [Self::default()];
 		source
 			.read_exact(bytemuck::cast_slice_mut(&mut header))
-			.unwrap();
+			.map_err(|_| Error::CorruptFile)?;
 		let mut header = header[0];
 		if header.legacy_point_amount != 0 {
 			header.number_of_point_records = header.legacy_point_amount as u64;
@@ -221,6 +409,111 @@ impl Header {
 		Ok(header)
 	}
 
+	/// Point format without the compression bits.
+	pub fn point_format(&self) -> u8 {
+		self.point_data_record_format & 0b0011_1111
+	}
+
+	/// LASzip marks compressed point data with the upper two bits of the point format.
+	pub fn is_compressed(&self) -> bool {
+		self.point_data_record_format & 0b1100_0000 != 0
+	}
+
+	pub fn scale(&self) -> Vector<3, f64> {
+		Vector::new([
+			self.x_scale_factor,
+			self.y_scale_factor,
+			self.z_scale_factor,
+		])
+	}
+
+	pub fn offset(&self) -> Vector<3, f64> {
+		Vector::new([self.x_offset, self.y_offset, self.z_offset])
+	}
+
+	pub fn bounds(&self) -> Bounds {
+		Bounds::from_source(
+			Vector::new([self.min_x, self.min_y, self.min_z]),
+			Vector::new([self.max_x, self.max_y, self.max_z]),
+		)
+	}
+
+	/// Variable length records followed by the extended records.
+	pub fn records<R: Seek + Read>(&self, mut source: R) -> Result<Vec<Record>, Error> {
+		fn read_record<R: Read>(source: &mut R, header: &[u8], length: usize, extended: bool) -> Result<Record, Error> {
+			let text = |range: std::ops::Range<usize>| {
+				String::from_utf8_lossy(&header[range])
+					.trim_end_matches('\0')
+					.to_owned()
+			};
+			let mut data = vec![0; length];
+			source.read_exact(&mut data)?;
+			Ok(Record {
+				user_id: text(2..18),
+				record_id: u16::from_le_bytes([header[18], header[19]]),
+				description: text(header.len() - 32..header.len()),
+				extended,
+				data,
+			})
+		}
+
+		let mut records = Vec::new();
+		source.seek(SeekFrom::Start(self.header_size as u64))?;
+		for _ in 0..self.number_of_variable_length_records {
+			let mut header = [0; 54];
+			source.read_exact(&mut header)?;
+			let length = u16::from_le_bytes([header[20], header[21]]) as usize;
+			records.push(read_record(&mut source, &header, length, false)?);
+		}
+		// extended records only exist since 1.4, older headers end before these fields
+		if self.version_minor >= 4 && self.number_of_extended_variable_length_records > 0 {
+			source.seek(SeekFrom::Start(self.first_vlr_offset))?;
+			for _ in 0..self.number_of_extended_variable_length_records {
+				let mut header = [0; 60];
+				source.read_exact(&mut header)?;
+				let length = u64::from_le_bytes(header[20..28].try_into().unwrap()) as usize;
+				records.push(read_record(&mut source, &header, length, true)?);
+			}
+		}
+		Ok(records)
+	}
+
+	/// Coordinate reference system from the WKT or GeoTIFF projection records.
+	pub fn crs<R: Seek + Read>(&self, source: R) -> Result<Option<String>, Error> {
+		let records = self.records(source)?;
+
+		let projection = |record_id: u16| {
+			records
+				.iter()
+				.find(|record| record.user_id == "LASF_Projection" && record.record_id == record_id)
+		};
+
+		if let Some(wkt) = projection(2112) {
+			let wkt = String::from_utf8_lossy(&wkt.data);
+			return Ok(Some(wkt.trim_end_matches('\0').to_owned()));
+		}
+
+		// GeoKeyDirectoryTag with the projected or geographic EPSG code
+		let Some(keys) = projection(34735) else {
+			return Ok(None);
+		};
+		let keys = keys
+			.data
+			.chunks_exact(2)
+			.map(|v| u16::from_le_bytes([v[0], v[1]]))
+			.collect::<Vec<_>>();
+		let code = |id: u16| {
+			keys.chunks_exact(4)
+				.skip(1)
+				.find(|key| key[0] == id && key[1] == 0)
+				.map(|key| key[3])
+				.filter(|&code| code != 0 && code != u16::MAX)
+		};
+		Ok(code(3072)
+			.or_else(|| code(2048))
+			.map(|code| format!("EPSG:{}", code)))
+	}
+
 	pub fn quick_header(&self) -> QuickHeader {
 		QuickHeader {
 			major: self.version_major,
@@ -1,6 +1,6 @@
 use math::{Vector, X, Y, Z};
 
-use crate::{point::PointsCollection, Settings};
+use crate::{attributes::ATTRIBUTES, point::PointsCollection, Settings};
 
 const GRID_SIZE: usize = 64;
 const GRID_SIZE_3: usize = GRID_SIZE * GRID_SIZE * GRID_SIZE;
@@ -16,6 +16,7 @@ struct Cell {
 	height: u32,
 	curve: u32,
 	segment: u32,
+	attributes: [u32; ATTRIBUTES],
 }
 
 pub fn grid(
@@ -37,6 +38,7 @@ pub fn grid(
 			height: 0,
 			curve: 0,
 			segment: 0,
+			attributes: [0; ATTRIBUTES],
 		},
 	);
 	let grid_scale = GRID_SIZE as f32 / size;
@@ -62,6 +64,9 @@ pub fn grid(
 			cell.height = points.height[i];
 			cell.curve = points.curve[i];
 			cell.segment = points.segment[i];
+			for (value, values) in cell.attributes.iter_mut().zip(&points.attributes) {
+				*value = values[i];
+			}
 		}
 	}
 
@@ -80,6 +85,7 @@ pub fn grid(
 			cell.height,
 			cell.curve,
 			cell.segment,
+			cell.attributes,
 		);
 	}
 	res
@@ -1,25 +1,51 @@
+mod attributes;
+mod batch;
 mod cache;
 mod calculations;
+mod config;
+mod debug;
+mod denoise;
+mod downsample;
+mod e57;
+mod evaluate;
+mod ground;
+mod inspect;
+mod labels;
+mod las;
 mod laz;
 mod level_of_detail;
+mod ordered;
+mod ply;
 mod point;
 mod progress;
 mod segment;
+mod source;
+mod tiled;
 mod tree;
+mod watershed;
 mod writer;
+mod xyz;
 
 use std::{num::NonZeroU32, path::PathBuf};
 
+pub use batch::{batch, BatchArguments, BatchCommand};
+use clap::Parser;
+pub use config::Preset;
+pub use evaluate::{evaluate, EvaluateCommand};
+pub use inspect::{inspect, InspectCommand, OutputFormat};
 use math::{X, Y, Z};
 use point::PointsCollection;
 use progress::Progress;
-use rand::seq::SliceRandom;
-use rayon::prelude::*;
+pub use progress::{Json, Observer, Silent, Terminal};
+use rand::{seq::SliceRandom, SeedableRng};
+pub use segment::Segmentation;
 use writer::Writer;
 
 use tree::Tree;
 
-use crate::{cache::Cache, progress::Stage, segment::Segmenter};
+use crate::{
+	cache::Cache, debug::SegmentationDebug, downsample::Downsample, ground::GroundGrid, progress::Stage, segment::Tiles,
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -32,93 +58,448 @@ pub enum Error {
 	#[error(transparent)]
 	InvalidFile(#[from] std::io::Error),
 
+	#[error("Could not spill the cache to disk: {0}")]
+	Spill(std::io::Error),
+
+	#[error("Could not read the spilled cache from disk: {0}")]
+	Unspill(std::io::Error),
+
 	#[error("Corrupt file")]
 	CorruptFile,
 
 	#[error(transparent)]
 	LasZipError(#[from] ::laz::LasZipError),
 
+	#[error(transparent)]
+	E57Error(#[from] ::e57::Error),
+
+	#[error(transparent)]
+	InvalidPattern(#[from] glob::PatternError),
+
+	#[error(transparent)]
+	Project(#[from] project::Error),
+
+	#[error("Inputs with different coordinate reference systems")]
+	DifferentCrs,
+
+	#[error("No field '{0}' with labels in the input")]
+	MissingLabel(String),
+
+	#[error("Unsupported input: {0}")]
+	UnsupportedFormat(String),
+
 	#[error("Output folder is file")]
 	OutputFolderIsFile,
 
 	#[error("Output folder is not empty")]
 	OutputFolderIsNotEmpty,
 
+	#[error("Folder '{0}' was not created by the importer")]
+	ForeignOutputFolder(PathBuf),
+
+	#[error("Invalid config: {0}")]
+	InvalidConfig(String),
+
+	#[error("{0} imports failed")]
+	BatchFailed(usize),
+
 	#[error("Atleast two Threads are required")]
 	NotEnoughThreads,
+
+	#[error(transparent)]
+	ThreadPool(#[from] rayon::ThreadPoolBuildError),
 }
 
-#[derive(clap::Args)]
+#[derive(Clone, clap::Args, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Settings {
-	/// Minimum size for segments. Segments with less points are removed.
+	/// Algorithm to split the points into trees
+	#[arg(long, value_enum, default_value_t = Segmentation::Slices)]
+	pub segmentation: Segmentation,
+
+	/// Use the tree labels from `classification`, `point-source-id` or a named extra bytes field
+	/// instead of segmenting. Points with the label 0 are unassigned.
+	#[arg(long)]
+	pub segment_from: Option<String>,
+
+	/// Minimum size for segments. Points of smaller segments are unassigned.
 	#[arg(long, default_value_t = 100)]
-	min_segment_size: usize,
+	pub min_segment_size: usize,
 
 	/// Width of the horizontal slice in meters
 	#[arg(long, default_value_t = 1.0)]
-	segmenting_slice_width: f32,
+	pub segmenting_slice_width: f32,
 
 	/// Distance to combine segments in meters
 	#[arg(long, default_value_t = 1.0)]
-	segmenting_max_distance: f32,
+	pub segmenting_max_distance: f32,
+
+	/// Segment in square tiles with this size in meters, so the memory depends on the tile size
+	/// instead of the area. The whole area at once if not specified.
+	#[arg(long)]
+	pub segmenting_tile_size: Option<f32>,
+
+	/// Buffer in meters around each tile, larger than the crown radius so trees crossing the
+	/// tile edges are complete
+	#[arg(long, default_value_t = 10.0)]
+	pub segmenting_tile_overlap: f32,
+
+	/// Cell size in meters for the canopy height model of `chm-watershed`
+	#[arg(long, default_value_t = 0.5)]
+	pub chm_cell_size: f32,
+
+	/// Minimum distance in meters between treetops for `chm-watershed`
+	#[arg(long, default_value_t = 2.0)]
+	pub chm_treetop_distance: f32,
+
+	/// Minimum height in meters above the ground for treetops and crowns for `chm-watershed`
+	#[arg(long, default_value_t = 2.0)]
+	pub chm_min_height: f32,
 
 	/// Maximum count for neighbors search
 	#[arg(long, default_value_t = 31)]
-	neighbors_count: usize,
+	pub neighbors_count: usize,
 
 	/// Maximum distance in meters for the neighbors search
 	#[arg(long, default_value_t = 1.0)]
-	neighbors_max_distance: f32,
+	pub neighbors_max_distance: f32,
 
 	/// Scale for the size of the combined point
 	#[arg(long, default_value_t = 0.95)]
-	lod_size_scale: f32,
+	pub lod_size_scale: f32,
+
+	/// Classify the ground and use the height above ground for segmenting
+	#[arg(long)]
+	pub ground_filter: bool,
+
+	/// Cell size in meters for the terrain model
+	#[arg(long, default_value_t = 1.0)]
+	pub ground_cell_size: f32,
+
+	/// Largest object in meters removed by the ground filter
+	#[arg(long, default_value_t = 20.0)]
+	pub ground_max_window: f32,
+
+	/// Expected terrain slope as height difference per meter
+	#[arg(long, default_value_t = 0.3)]
+	pub ground_slope: f32,
+
+	/// Points up to this height in meters above the terrain model are ground
+	#[arg(long, default_value_t = 0.3)]
+	pub ground_threshold: f32,
+
+	/// Keep one point per voxel with this size in meters
+	#[arg(long)]
+	pub voxel_size: Option<f32>,
+
+	/// Minimum distance in meters between the kept points
+	#[arg(long)]
+	pub min_spacing: Option<f32>,
+
+	/// Remove points with a large mean distance to their nearest neighbors
+	#[arg(long)]
+	pub statistical_outlier_filter: bool,
+
+	/// Nearest neighbors for the mean distance of the statistical outlier filter
+	#[arg(long, default_value_t = 8)]
+	pub statistical_outlier_neighbors: usize,
+
+	/// Allowed standard deviations above the average mean distance
+	#[arg(long, default_value_t = 2.0)]
+	pub statistical_outlier_deviations: f32,
+
+	/// Remove points with not enough neighbors in the search radius
+	#[arg(long)]
+	pub radius_outlier_filter: bool,
+
+	/// Search radius in meters for the radius outlier filter
+	#[arg(long, default_value_t = 0.5)]
+	pub radius_outlier_distance: f32,
+
+	/// Minimum neighbors in the search radius
+	#[arg(long, default_value_t = 4)]
+	pub radius_outlier_neighbors: usize,
+
+	/// Memory budget in bytes for the points during the import, like `500M` or `8G`
+	#[arg(long, default_value = "4G", value_parser = parse_bytes)]
+	pub cache_size: usize,
+
+	/// Folder for the points exceeding the memory budget. System temp folder if not specified.
+	#[arg(long)]
+	pub spill_folder: Option<PathBuf>,
+
+	/// Shuffle the segment numbers with the seed. Numbered by the size and stem position if not specified.
+	#[arg(long)]
+	pub seed: Option<u64>,
+
+	/// Save the hulls, stems and Voronoi cells of every slice as GeoJSON in this folder
+	#[arg(long)]
+	pub debug_segmentation: Option<PathBuf>,
+}
+
+impl Default for Settings {
+	fn default() -> Self {
+		Defaults::parse_from([""]).settings
+	}
+}
+
+/// Layout for text based inputs like `.xyz`, `.txt` and `.csv`.
+#[derive(Clone, clap::Args)]
+pub struct TextFormat {
+	/// Columns for the x, y and z coordinates, starting at 0
+	#[arg(long, default_value = "0,1,2", value_parser = parse_columns)]
+	pub text_columns: [usize; 3],
+
+	/// Delimiter between columns. Whitespace if not specified, `,` for `.csv`.
+	#[arg(long)]
+	pub text_delimiter: Option<char>,
+
+	/// Lines to skip at the start. A header line with column names is skipped without it.
+	#[arg(long, default_value_t = 0)]
+	pub text_skip_lines: usize,
+
+	/// Columns for the red, green and blue values, starting at 0
+	#[arg(long, value_parser = parse_columns)]
+	pub text_color_columns: Option<[usize; 3]>,
+
+	/// Factor for the color values, like `65535` for values between 0 and 1
+	#[arg(long, default_value_t = 1.0)]
+	pub text_color_scale: f64,
+
+	/// Column for the intensity, starting at 0
+	#[arg(long)]
+	pub text_intensity_column: Option<usize>,
+}
+
+impl Default for TextFormat {
+	fn default() -> Self {
+		Defaults::parse_from([""]).text
+	}
 }
 
+/// Default values from the command line arguments.
 #[derive(clap::Parser)]
-pub struct Command {
-	/// Input file location. Open File Dialog if not specified.
-	input_file: Option<PathBuf>,
+struct Defaults {
+	#[command(flatten)]
+	settings: Settings,
+
+	#[command(flatten)]
+	text: TextFormat,
+}
+
+/// Byte count with an optional `K`, `M`, `G` or `T` suffix for powers of 1000.
+fn parse_bytes(value: &str) -> Result<usize, String> {
+	let value = value.trim();
+	let (number, scale) = match value.chars().last().map(|unit| unit.to_ascii_uppercase()) {
+		Some('K') => (&value[..value.len() - 1], 1e3),
+		Some('M') => (&value[..value.len() - 1], 1e6),
+		Some('G') => (&value[..value.len() - 1], 1e9),
+		Some('T') => (&value[..value.len() - 1], 1e12),
+		_ => (value, 1.0),
+	};
+	let number = number
+		.trim()
+		.parse::<f64>()
+		.map_err(|err| err.to_string())?;
+	if number.is_nan() || number < 0.0 {
+		return Err(String::from("expected a positive size like `4G`"));
+	}
+	Ok((number * scale) as usize)
+}
+
+fn parse_columns(value: &str) -> Result<[usize; 3], String> {
+	let columns = value
+		.split(',')
+		.map(|column| {
+			column
+				.trim()
+				.parse::<usize>()
+				.map_err(|err| err.to_string())
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+	columns
+		.try_into()
+		.map_err(|_| String::from("expected three columns like `0,1,2`"))
+}
+
+#[derive(clap::Parser)]
+pub struct ImportArguments {
+	/// Input files (`.las`, `.laz`, `.ply`, `.xyz`, `.txt`, `.csv` or `.e57`), folders or glob patterns.
+	/// Multiple inputs are combined into one project. Open File Dialog if not specified.
+	input_files: Vec<PathBuf>,
 
 	/// Output folder location. Open File Dialog if not specified.
 	#[arg(long, short)]
 	output_folder: Option<PathBuf>,
 
-	/// Maximal thread count for multithreading. 0 for the amount of logical cores.
+	/// Format for the progress output
+	#[arg(long, value_enum, default_value_t = ProgressFormat::Terminal)]
+	progress: ProgressFormat,
+
+	/// Write the JSON progress to a file instead of the standard output
+	#[arg(long)]
+	progress_file: Option<PathBuf>,
+
+	#[command(flatten)]
+	options: Options,
+}
+
+/// Options for every import.
+#[derive(clap::Args)]
+struct Options {
+	/// Maximal thread count for multithreading. 0 for the amount of logical cores, but at least two.
 	#[arg(long, default_value_t = 0)]
 	max_threads: usize,
 
+	/// Settings file (`.toml` or `.json`), overwrites the preset
+	#[arg(long)]
+	config: Option<PathBuf>,
+
+	/// Settings for the scanner type, used as defaults
+	#[arg(long, value_enum)]
+	preset: Option<Preset>,
+
 	#[command(flatten)]
 	settings: Settings,
+
+	#[command(flatten)]
+	disable: config::Disable,
+
+	#[command(flatten)]
+	text: TextFormat,
+}
+
+impl Options {
+	/// Settings from the preset, settings file and explicit arguments.
+	fn settings(&self, explicit: &[String]) -> Result<Settings, Error> {
+		let mut settings = config::resolve(
+			&self.settings,
+			explicit,
+			self.preset,
+			self.config.as_deref(),
+		)?;
+		self.disable.apply(&mut settings);
+		Ok(settings)
+	}
+}
+
+/// Command line arguments, which remember the explicit values.
+///
+/// Only the explicit arguments overwrite the preset and settings file.
+pub struct Explicit<T> {
+	arguments: T,
+	explicit: Vec<String>,
+}
+
+/// Command line arguments for the importer.
+pub type Command = Explicit<ImportArguments>;
+
+impl<T: clap::FromArgMatches> clap::FromArgMatches for Explicit<T> {
+	fn from_arg_matches(matches: &clap::ArgMatches) -> Result<Self, clap::Error> {
+		Ok(Self {
+			arguments: T::from_arg_matches(matches)?,
+			explicit: explicit(matches),
+		})
+	}
+
+	fn update_from_arg_matches(&mut self, matches: &clap::ArgMatches) -> Result<(), clap::Error> {
+		self.arguments.update_from_arg_matches(matches)?;
+		self.explicit.extend(explicit(matches));
+		Ok(())
+	}
+}
+
+fn explicit(matches: &clap::ArgMatches) -> Vec<String> {
+	matches
+		.ids()
+		.filter(|id| matches.value_source(id.as_str()) == Some(clap::parser::ValueSource::CommandLine))
+		.map(|id| id.to_string())
+		.collect()
+}
+
+impl<T: clap::Args> clap::Args for Explicit<T> {
+	fn augment_args(command: clap::Command) -> clap::Command {
+		T::augment_args(command)
+	}
+
+	fn augment_args_for_update(command: clap::Command) -> clap::Command {
+		T::augment_args_for_update(command)
+	}
+}
+
+impl<T: clap::CommandFactory> clap::CommandFactory for Explicit<T> {
+	fn command() -> clap::Command {
+		T::command()
+	}
+
+	fn command_for_update() -> clap::Command {
+		T::command_for_update()
+	}
 }
 
+impl<T: clap::Parser> clap::Parser for Explicit<T> {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressFormat {
+	/// Progress bars
+	Terminal,
+	/// One JSON object per line for every event
+	Json,
+}
+
+/// Point counts, cache usage and stage durations of an import.
 #[derive(Default, serde::Serialize)]
 pub struct Statistics {
-	source_points: usize,
-	leaf_points: usize,
-	branch_points: usize,
-	segments: usize,
-	times: Times,
+	/// Points in the input files
+	pub source_points: usize,
+	/// Points left after downsampling
+	pub downsampled_points: usize,
+	pub statistical_outliers: usize,
+	pub radius_outliers: usize,
+	pub ground_points: usize,
+	/// Points without a tree
+	pub unassigned_points: usize,
+	/// Points in the leaf nodes of the project
+	pub leaf_points: usize,
+	/// Combined points in the branch nodes for the level of detail
+	pub branch_points: usize,
+	pub segments: usize,
+	/// Most bytes held in memory by the cache
+	pub cache_peak: usize,
+	/// Bytes written to the spill files
+	pub cache_spilled: usize,
+	/// Most bytes above the cache budget, if the entries were too small to spill
+	pub cache_over_budget: usize,
+	pub times: Times,
 }
 
+/// Duration of the import stages in seconds.
 #[derive(Default, serde::Serialize)]
 pub struct Times {
-	setup: f32,
-	import: f32,
-	segment: f32,
-	calculate: f32,
-	project: f32,
-	lods: f32,
+	pub setup: f32,
+	pub import: f32,
+	pub downsample: f32,
+	pub denoise: f32,
+	pub ground: f32,
+	pub segment: f32,
+	pub calculate: f32,
+	pub project: f32,
+	pub lods: f32,
 }
 
+/// Run the importer from the command line, missing paths are selected with file dialogs.
 pub fn run(command: Command) -> Result<(), Error> {
-	let input = match command.input_file {
-		Some(file) => file,
-		None => rfd::FileDialog::new()
-			.set_title("Select Input File")
-			.add_filter("Input File", &["las", "laz"])
-			.pick_file()
-			.ok_or(Error::NoInputFile)?,
+	let settings = command.arguments.options.settings(&command.explicit)?;
+	let command = command.arguments;
+
+	let input = if command.input_files.is_empty() {
+		rfd::FileDialog::new()
+			.set_title("Select Input Files")
+			.add_filter("Input File", &source::EXTENSIONS)
+			.pick_files()
+			.ok_or(Error::NoInputFile)?
+	} else {
+		command.input_files
 	};
 
 	let output = match command.output_folder {
@@ -129,131 +510,377 @@ pub fn run(command: Command) -> Result<(), Error> {
 			.ok_or(Error::NoOutputFolder)?,
 	};
 
-	if command.max_threads == 1 {
-		return Err(Error::NotEnoughThreads);
+	let importer = Importer::new(input, output)
+		.settings(settings)
+		.text_format(command.options.text)
+		.threads(command.options.max_threads);
+
+	match command.progress {
+		ProgressFormat::Terminal => importer.run(&Terminal).map(|_| ()),
+		ProgressFormat::Json => {
+			let json = match &command.progress_file {
+				Some(path) => Json::file(path)?,
+				None => Json::stdout(),
+			};
+			importer.run(&json).map(|_| ()).map_err(|err| {
+				json.error(&err);
+				err
+			})
+		},
+	}
+}
+
+/// Importer without any user interaction.
+///
+/// ```no_run
+/// let importer = importer::Importer::new(vec!["scan.laz".into()], "output".into()).threads(4);
+/// let statistics = importer.run(&importer::Silent)?;
+/// println!("{} trees", statistics.segments);
+/// # Ok::<(), importer::Error>(())
+/// ```
+pub struct Importer {
+	input: Vec<PathBuf>,
+	output: PathBuf,
+	settings: Settings,
+	text: TextFormat,
+	threads: usize,
+}
+
+impl Importer {
+	/// Input files, folders or glob patterns and the output folder.
+	pub fn new(input: Vec<PathBuf>, output: PathBuf) -> Self {
+		Self {
+			input,
+			output,
+			settings: Settings::default(),
+			text: TextFormat::default(),
+			threads: 0,
+		}
 	}
 
-	let settings = command.settings;
+	pub fn settings(mut self, settings: Settings) -> Self {
+		self.settings = settings;
+		self
+	}
+
+	pub fn text_format(mut self, text: TextFormat) -> Self {
+		self.text = text;
+		self
+	}
 
-	rayon::ThreadPoolBuilder::new()
-		.num_threads(command.max_threads)
-		.build()
-		.unwrap()
-		.install(|| import(settings, input, output))
+	/// Maximal thread count, 0 for the amount of logical cores but at least two.
+	pub fn threads(mut self, threads: usize) -> Self {
+		self.threads = threads;
+		self
+	}
+
+	/// Import the points, the statistics are also saved in the output folder.
+	pub fn run(self, observer: &dyn Observer) -> Result<Statistics, Error> {
+		let input = source::inputs(&self.input)?;
+
+		// the segmenting waits on other threads, so a single core still gets two threads
+		let threads = match self.threads {
+			0 => std::thread::available_parallelism().map_or(2, |cores| cores.get().max(2)),
+			threads => threads,
+		};
+		let pool = rayon::ThreadPoolBuilder::new()
+			.num_threads(threads)
+			.build()?;
+		if pool.current_num_threads() < 2 {
+			return Err(Error::NotEnoughThreads);
+		}
+		pool.install(|| import(self.settings, &self.text, input, self.output, observer))
+	}
 }
 
-fn import(settings: Settings, input: PathBuf, output: PathBuf) -> Result<(), Error> {
-	let mut cache = Cache::new(4_000_000_000);
+fn import(
+	settings: Settings,
+	text: &TextFormat,
+	input: Vec<PathBuf>,
+	output: PathBuf,
+	observer: &dyn Observer,
+) -> Result<Statistics, Error> {
+	if let Some(folder) = &settings.spill_folder {
+		std::fs::create_dir_all(folder)?;
+	}
+	let mut cache = Cache::new(settings.cache_size, settings.spill_folder.clone());
 	let mut statistics = Statistics::default();
-	let stage = Stage::new("Setup Files");
+	let stage = Stage::new("Setup Files", observer);
 
 	Writer::setup(&output)?;
-
-	let laz = laz::Laz::new(&input)?;
-	let min = laz.min;
-	let max = laz.max;
-	let diff = max - min;
-	let total_points = laz.total;
+	let file = std::fs::File::create(output.join("settings.json"))?;
+	serde_json::to_writer_pretty(file, &settings).map_err(std::io::Error::from)?;
+
+	let source = source::open(&input, text, settings.segment_from.as_deref())?;
+	let bounds = source.bounds();
+	let (mut min, mut max) = bounds.local();
+	let total_points = source.total();
+	let attributes = source.attributes();
+	let georeference = project::Georeference {
+		origin: project::Axes::ZUp.to_source(bounds.center()),
+		axes: project::Axes::ZUp,
+		crs: source.crs(),
+	};
 	statistics.source_points = total_points;
 
 	statistics.times.setup = stage.finish();
 
-	let mut progress = Progress::new("Import", total_points);
+	let mut progress = Progress::new("Import", total_points, observer);
+
+	let debug = SegmentationDebug::new(&settings, &georeference)?;
+	let mut segmenter = segment::new(min, max, &mut cache, &settings, debug.clone());
+
+	let mut range = attributes::Range::new();
 
-	let mut segmenter = Segmenter::new(min, max, &mut cache, &settings);
+	let mut downsample = Downsample::new(&settings);
+
+	// with downsampling, denoising or the ground filter all points are kept until the filters are done
+	let buffered = downsample.is_some()
+		|| settings.statistical_outlier_filter
+		|| settings.radius_outlier_filter
+		|| settings.ground_filter;
+	let mut batches = Vec::new();
 
 	let (sender, reciever) = crossbeam::channel::bounded(4);
 
-	rayon::join(
+	let (read, consumed) = rayon::join(
 		|| {
-			laz.read(|chunk| sender.send(chunk).unwrap())?;
+			source.read(bounds.center(), &|chunk| {
+				sender.send(chunk).unwrap();
+			})?;
 			drop(sender);
 			Result::<(), Error>::Ok(())
 		},
 		|| {
-			for chunk in reciever {
+			let mut reciever = reciever.into_iter();
+			let result = reciever.by_ref().try_for_each(|chunk| {
 				let l = chunk.length();
-				for point in chunk {
-					segmenter.add_point(point, &mut cache);
+				match &mut downsample {
+					// the downsampled points are counted after reducing the tiles
+					Some(downsample) => {
+						for point in chunk {
+							range.insert(&point.attributes);
+							downsample.add_point(point, &mut cache)?;
+						}
+					},
+					None if buffered => {
+						statistics.downsampled_points += l;
+						let points = chunk
+							.into_iter()
+							.inspect(|point| range.insert(&point.attributes))
+							.collect();
+						let batch = cache.new_entry();
+						cache.add_chunk(&batch, points)?;
+						batches.push(batch);
+					},
+					None => {
+						statistics.downsampled_points += l;
+						for point in chunk {
+							range.insert(&point.attributes);
+							segmenter.add_point(point, point.position[Y] - min[Y], &mut cache)?;
+						}
+					},
 				}
 				progress.step_by(l);
-			}
+				Result::<(), Error>::Ok(())
+			});
+			// after an error the source still sends the rest of the chunks
+			reciever.for_each(drop);
+			result
 		},
-	)
-	.0?;
+	);
+	read?;
+	consumed?;
 
 	statistics.times.import = progress.finish();
 
-	let mut segments = segmenter.segments(&mut statistics, &mut cache);
+	if let Some(downsample) = downsample {
+		batches = downsample.reduce(&mut cache, &mut statistics, observer)?;
+	}
+
+	if settings.statistical_outlier_filter || settings.radius_outlier_filter {
+		batches = denoise::denoise(
+			batches,
+			(&mut min, &mut max),
+			&settings,
+			&mut cache,
+			&mut statistics,
+			observer,
+		)?;
+	}
+	if buffered {
+		// the segmenter from the source bounds is still empty
+		segmenter = segment::new(min, max, &mut cache, &settings, debug);
+	}
+
+	// the points left after downsampling and denoising
+	let remaining = statistics.downsampled_points - statistics.radius_outliers - statistics.statistical_outliers;
+
+	let mut ground_tiles = Tiles::new(min);
+	let dtm = if settings.ground_filter {
+		let mut progress = Progress::new("Ground", remaining, observer);
+		let mut ground = GroundGrid::new(min, max, settings.ground_cell_size);
+		let batches = batches
+			.into_iter()
+			.map(|batch| {
+				let points = cache.read(batch).read()?;
+				for point in &points {
+					ground.insert(point.position);
+				}
+				let batch = cache.new_entry();
+				cache.add_chunk(&batch, points)?;
+				Ok(batch)
+			})
+			.collect::<Result<Vec<_>, Error>>()?;
+		let dtm = ground.dtm(&settings);
+		for batch in batches {
+			let points = cache.read(batch).read()?;
+			let l = points.len();
+			for point in points {
+				let height = dtm.above(point.position);
+				if height < settings.ground_threshold {
+					statistics.ground_points += 1;
+					ground_tiles.add_point(point, &mut cache)?;
+					continue;
+				}
+				segmenter.add_point(point, height, &mut cache)?;
+			}
+			progress.step_by(l);
+		}
+		dtm.save(&output.join("dtm.asc"), &georeference)?;
+		statistics.times.ground = progress.finish();
+		Some(dtm)
+	} else {
+		for batch in batches {
+			for point in cache.read(batch).read()? {
+				segmenter.add_point(point, point.position[Y] - min[Y], &mut cache)?;
+			}
+		}
+		None
+	};
+	let mut unassigned_tiles = Tiles::new(min);
+	let mut segments = segmenter.segments(&mut unassigned_tiles, &mut statistics, &mut cache, observer)?;
 	statistics.segments = segments.len();
-	segments.shuffle(&mut rand::thread_rng());
+	statistics.unassigned_points = unassigned_tiles.length();
+	// number the largest segments first and equal sizes west to east by their stem, so the same input
+	// gets the same numbers, the sort is stable and keeps the order of the segmenter for the rest
+	segments.sort_by(|a, b| {
+		b.length()
+			.cmp(&a.length())
+			.then(a.stem()[X].total_cmp(&b.stem()[X]))
+			.then(a.stem()[Y].total_cmp(&b.stem()[Y]))
+	});
+	if let Some(seed) = settings.seed {
+		segments.shuffle(&mut rand::rngs::StdRng::seed_from_u64(seed));
+	}
 
-	let mut progress = Progress::new("Calculate", total_points);
+	let mut progress = Progress::new("Calculate", remaining, observer);
 
+	let diff = max - min;
 	let mut tree = Tree::new(min, diff[X].max(diff[Y]).max(diff[Z]));
-	let segments_information = vec![String::from("Trunk"), String::from("Crown")];
+	let mut segments_information = vec![String::from("Trunk"), String::from("Crown")];
+	if settings.segment_from.is_some() {
+		segments_information.push(String::from("Label"));
+	}
 
-	let (sender, reciever) = crossbeam::channel::bounded(2);
-	let (_, segment_values) = rayon::join(
-		|| {
-			segments
-				.into_par_iter()
-				.enumerate()
-				.for_each(|(index, segment)| {
-					let index = NonZeroU32::new(index as u32 + 1).unwrap();
-					let (points, information) = calculations::calculate(segment.points(), index, &settings);
-					sender.send((points, index, information)).unwrap();
-				});
-			drop(sender);
+	let mut path = output.clone();
+	path.push("segments");
+	std::fs::create_dir(&path)?;
+	let mut segment_writer = Writer::new(path, statistics.segments, &attributes)?;
+	let mut segment_values = vec![project::Value::Percent(0.0); statistics.segments * segments_information.len()];
+	// points without a tree are kept with a reserved segment, but without a segment file
+	let unassigned = NonZeroU32::new(project::UNASSIGNED_SEGMENT).unwrap();
+	let ground = NonZeroU32::new(project::GROUND_SEGMENT).unwrap();
+	let items = segments
+		.into_iter()
+		.enumerate()
+		.map(|(index, segment)| {
+			if let Some(label) = segment.label() {
+				segment_values[index * segments_information.len() + 2] = project::Value::Index(label);
+			}
+			(NonZeroU32::new(index as u32 + 1).unwrap(), segment.data())
+		})
+		.chain(
+			unassigned_tiles
+				.entries(&mut cache)
+				.into_iter()
+				.map(|entry| (unassigned, entry)),
+		)
+		.chain(
+			ground_tiles
+				.entries(&mut cache)
+				.into_iter()
+				.map(|entry| (ground, entry)),
+		)
+		.collect::<Vec<_>>();
+	ordered::try_for_each(
+		items,
+		|(segment, data)| {
+			let mut points = data.read()?;
+			// the slices are segmented in parallel, so the order of the points changes between runs
+			points.sort_by(|a, b| {
+				let (a, b) = (a.position, b.position);
+				a[X].total_cmp(&b[X])
+					.then(a[Y].total_cmp(&b[Y]))
+					.then(a[Z].total_cmp(&b[Z]))
+			});
+			let (points, information) = calculations::calculate(points, segment, &settings, &range, dtm.as_ref());
+			Result::<_, Error>::Ok((points, segment, information))
 		},
-		|| {
-			let mut path = output.clone();
-			path.push("segments");
-			std::fs::create_dir(&path).unwrap();
-			let mut segment_writer = Writer::new(path, statistics.segments);
-			let mut segment_values =
-				vec![project::Value::Percent(0.0); statistics.segments * segments_information.len()];
-			for (points, segment, information) in reciever {
+		|(points, segment, information)| {
+			if !project::is_reserved_segment(segment.get()) {
 				let collection = PointsCollection::from_points(&points);
-				segment_writer.save(segment.get() as usize - 1, &collection);
+				segment_writer.save(segment.get() as usize - 1, &collection)?;
 				let offset = (segment.get() - 1) as usize;
 				segment_values[offset * segments_information.len()] = information.trunk_height;
 				segment_values[offset * segments_information.len() + 1] = information.crown_height;
-				let l = points.len();
-				for point in points {
-					tree.insert(point, &mut cache);
-				}
-				progress.step_by(l);
 			}
-			segment_values
+			let l = points.len();
+			for point in points {
+				tree.insert(point, &mut cache)?;
+			}
+			progress.step_by(l);
+			Ok(())
 		},
-	);
+	)?;
 
 	statistics.times.calculate = progress.finish();
 
-	let stage = Stage::new("Save Project");
+	let stage = Stage::new("Save Project", observer);
 
-	let properties = [
+	let mut properties = vec![
 		("segment", "Segment", statistics.segments as u32),
 		("height", "Height", u32::MAX),
 		("slice", "Expansion", u32::MAX),
 		("curve", "Curvature", u32::MAX),
 	];
+	for &attribute in &attributes {
+		let (file, name) = attribute.property();
+		properties.push((file, name, range.scale(attribute)));
+	}
+
+	let name = match input.as_slice() {
+		[file] => file.display().to_string(),
+		files => format!("{} ({} files)", files[0].display(), files.len()),
+	};
+
+	statistics.cache_peak = cache.peak();
+	statistics.cache_spilled = cache.spilled();
+	statistics.cache_over_budget = cache.over_budget();
 
 	let (tree, project) = tree.flatten(
 		&properties,
-		input.display().to_string(),
+		name,
 		cache,
 		segments_information,
 		segment_values,
+		georeference,
 	);
 
-	let mut writer = Writer::new(output, project.root.index as usize + 1);
-	writer.save_project(&project);
+	let mut writer = Writer::new(output, project.root.index as usize + 1, &attributes)?;
+	writer.save_project(&project)?;
 
 	statistics.times.project = stage.finish();
 
-	tree.save(writer, &settings, statistics);
-
-	Ok(())
+	tree.save(writer, &settings, statistics, observer)
 }
@@ -0,0 +1,38 @@
+use rayon::prelude::*;
+
+/// Items mapped in parallel at once.
+pub const WINDOW: usize = 64;
+
+/// Map the items in parallel and consume the results in the order of the items.
+///
+/// Only a window of items is mapped at once, so the results don't have to fit into memory.
+/// The next window is mapped while the results of the current window are consumed.
+pub fn try_for_each<T, R, E>(
+	items: impl IntoIterator<Item = T>,
+	map: impl Fn(T) -> Result<R, E> + Sync,
+	mut consume: impl FnMut(R) -> Result<(), E> + Send,
+) -> Result<(), E>
+where
+	T: Send,
+	R: Send,
+	E: Send,
+{
+	let mut items = items.into_iter();
+	let mut current = Vec::new();
+	loop {
+		let window = items.by_ref().take(WINDOW).collect::<Vec<_>>();
+		if window.is_empty() && current.is_empty() {
+			return Ok(());
+		}
+		let (next, result) = rayon::join(
+			|| window.into_par_iter().map(&map).collect::<Vec<_>>(),
+			|| {
+				current
+					.drain(..)
+					.try_for_each(|result: Result<R, E>| consume(result?))
+			},
+		);
+		result?;
+		current = next;
+	}
+}
@@ -0,0 +1,335 @@
+use math::Vector;
+use std::{
+	io::{BufRead, BufReader, Read, Seek, SeekFrom},
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	attributes::{Attribute, Attributes},
+	ordered,
+	source::{to_importer, Bounds, Chunk, Source, SourcePoint, CHUNK_SIZE},
+	xyz::{Columns, Xyz},
+	Error,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+	Ascii,
+	LittleEndian,
+	BigEndian,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Type {
+	I8,
+	U8,
+	I16,
+	U16,
+	I32,
+	U32,
+	F32,
+	F64,
+}
+
+impl Type {
+	fn parse(name: &str) -> Result<Self, Error> {
+		Ok(match name {
+			"char" | "int8" => Self::I8,
+			"uchar" | "uint8" => Self::U8,
+			"short" | "int16" => Self::I16,
+			"ushort" | "uint16" => Self::U16,
+			"int" | "int32" => Self::I32,
+			"uint" | "uint32" => Self::U32,
+			"float" | "float32" => Self::F32,
+			"double" | "float64" => Self::F64,
+			_ => return Err(Error::CorruptFile),
+		})
+	}
+
+	fn size(self) -> usize {
+		match self {
+			Self::I8 | Self::U8 => 1,
+			Self::I16 | Self::U16 => 2,
+			Self::I32 | Self::U32 | Self::F32 => 4,
+			Self::F64 => 8,
+		}
+	}
+
+	/// Factor to 16 bit attribute values, floating point values are expected between 0 and 1.
+	fn scale(self) -> f64 {
+		match self {
+			Self::F32 | Self::F64 => u16::MAX as f64,
+			_ => 1.0,
+		}
+	}
+
+	fn read(self, data: &[u8], encoding: Encoding) -> f64 {
+		macro_rules! read {
+			($t:ty) => {{
+				let bytes = data[0..std::mem::size_of::<$t>()].try_into().unwrap();
+				match encoding {
+					Encoding::BigEndian => <$t>::from_be_bytes(bytes) as f64,
+					_ => <$t>::from_le_bytes(bytes) as f64,
+				}
+			}};
+		}
+		match self {
+			Self::I8 => read!(i8),
+			Self::U8 => read!(u8),
+			Self::I16 => read!(i16),
+			Self::U16 => read!(u16),
+			Self::I32 => read!(i32),
+			Self::U32 => read!(u32),
+			Self::F32 => read!(f32),
+			Self::F64 => read!(f64),
+		}
+	}
+}
+
+/// Open a ply file, ascii files are read as delimited text.
+pub fn open(path: &Path) -> Result<Box<dyn Source>, Error> {
+	let mut file = BufReader::new(std::fs::File::open(path)?);
+	let mut line = String::new();
+	let mut position = 0;
+	let mut next_line = |line: &mut String| -> Result<(), Error> {
+		line.clear();
+		let length = file.read_line(line)?;
+		if length == 0 {
+			return Err(Error::CorruptFile);
+		}
+		position += length as u64;
+		Ok(())
+	};
+
+	next_line(&mut line)?;
+	if line.trim_end() != "ply" {
+		return Err(Error::CorruptFile);
+	}
+
+	let mut encoding = None;
+	let mut vertices = None;
+	let mut properties = Vec::new();
+	let mut current_is_vertex = false;
+	loop {
+		next_line(&mut line)?;
+		let mut words = line.split_whitespace();
+		match words.next() {
+			Some("format") => {
+				encoding = Some(match words.next() {
+					Some("ascii") => Encoding::Ascii,
+					Some("binary_little_endian") => Encoding::LittleEndian,
+					Some("binary_big_endian") => Encoding::BigEndian,
+					_ => return Err(Error::CorruptFile),
+				});
+			},
+			Some("element") => {
+				if vertices.is_some() {
+					current_is_vertex = false;
+					continue;
+				}
+				let name = words.next().ok_or(Error::CorruptFile)?;
+				if name != "vertex" {
+					return Err(Error::UnsupportedFormat(format!(
+						"ply element '{}' before vertices",
+						name
+					)));
+				}
+				let count = words
+					.next()
+					.and_then(|count| count.parse::<usize>().ok())
+					.ok_or(Error::CorruptFile)?;
+				vertices = Some(count);
+				current_is_vertex = true;
+			},
+			Some("property") if current_is_vertex => {
+				let ty = words.next().ok_or(Error::CorruptFile)?;
+				if ty == "list" {
+					return Err(Error::UnsupportedFormat(String::from(
+						"ply list property for vertices",
+					)));
+				}
+				let name = words.next().ok_or(Error::CorruptFile)?;
+				properties.push((String::from(name), Type::parse(ty)?));
+			},
+			Some("end_header") => break,
+			_ => {},
+		}
+	}
+
+	let encoding = encoding.ok_or(Error::CorruptFile)?;
+	let vertices = vertices.ok_or(Error::CorruptFile)?;
+	let index = |name: &str| {
+		properties
+			.iter()
+			.position(|(property, _)| property == name)
+			.ok_or(Error::CorruptFile)
+	};
+	let indices = [index("x")?, index("y")?, index("z")?];
+	let optional = |names: &[&str]| {
+		properties
+			.iter()
+			.position(|(property, _)| names.iter().any(|name| property.eq_ignore_ascii_case(name)))
+	};
+	let color = match [
+		optional(&["red"]),
+		optional(&["green"]),
+		optional(&["blue"]),
+	] {
+		[Some(red), Some(green), Some(blue)] => Some([red, green, blue]),
+		_ => None,
+	};
+	let intensity = optional(&["intensity", "scalar_intensity"]);
+
+	if encoding == Encoding::Ascii {
+		let columns = Columns {
+			color: color.map(|color| (color, properties[color[0]].1.scale())),
+			intensity: intensity.map(|intensity| (intensity, properties[intensity].1.scale())),
+			..Columns::new(indices, None)
+		};
+		return Ok(Box::new(Xyz::scan(
+			path,
+			position,
+			0,
+			Some(vertices),
+			columns,
+		)?));
+	}
+
+	let mut offsets = Vec::with_capacity(properties.len());
+	let mut point_length = 0;
+	for (_, ty) in &properties {
+		offsets.push(point_length);
+		point_length += ty.size();
+	}
+	let field = |index: usize| (offsets[index], properties[index].1);
+	let format = Format {
+		encoding,
+		point_length,
+		position: indices.map(field),
+		color: color.map(|color| color.map(field)),
+		intensity: intensity.map(field),
+	};
+
+	let mut file = file.into_inner();
+	file.seek(SeekFrom::Start(position))?;
+	let mut file = BufReader::new(file);
+	let mut bounds = Bounds::new();
+	let mut record = vec![0; point_length];
+	for _ in 0..vertices {
+		file.read_exact(&mut record)?;
+		bounds.insert(to_importer(format.position(&record)));
+	}
+	if bounds.is_empty() {
+		bounds.insert(Vector::default());
+	}
+
+	let chunks = (0..vertices)
+		.step_by(CHUNK_SIZE)
+		.map(|index| {
+			(
+				position + (index * point_length) as u64,
+				CHUNK_SIZE.min(vertices - index),
+			)
+		})
+		.collect::<Vec<_>>();
+
+	Ok(Box::new(Ply {
+		total: vertices,
+		chunks,
+		format,
+		bounds,
+		path: path.to_owned(),
+	}))
+}
+
+/// Offset and type of the fields in a vertex record.
+struct Format {
+	encoding: Encoding,
+	point_length: usize,
+	position: [(usize, Type); 3],
+	color: Option<[(usize, Type); 3]>,
+	intensity: Option<(usize, Type)>,
+}
+
+impl Format {
+	fn attributes(&self, record: &[u8]) -> Attributes {
+		let value = |(offset, ty): (usize, Type)| {
+			(ty.read(&record[offset..], self.encoding) * ty.scale()).clamp(0.0, u16::MAX as f64) as u16
+		};
+		let mut attributes = Attributes::default();
+		if let Some(color) = self.color {
+			attributes.color = color.map(value);
+		}
+		if let Some(intensity) = self.intensity {
+			attributes.intensity = value(intensity);
+		}
+		attributes
+	}
+
+	fn position(&self, record: &[u8]) -> Vector<3, f64> {
+		let [(x_offset, x), (y_offset, y), (z_offset, z)] = self.position;
+		Vector::new([
+			x.read(&record[x_offset..], self.encoding),
+			y.read(&record[y_offset..], self.encoding),
+			z.read(&record[z_offset..], self.encoding),
+		])
+	}
+}
+
+/// Binary ply file with fixed size vertex records.
+pub struct Ply {
+	total: usize,
+	chunks: Vec<(u64, usize)>,
+	format: Format,
+	bounds: Bounds,
+
+	path: PathBuf,
+}
+
+impl Source for Ply {
+	fn bounds(&self) -> Bounds {
+		self.bounds
+	}
+
+	fn total(&self) -> usize {
+		self.total
+	}
+
+	fn attributes(&self) -> Vec<Attribute> {
+		let mut attributes = Vec::new();
+		if self.format.intensity.is_some() {
+			attributes.push(Attribute::Intensity);
+		}
+		if self.format.color.is_some() {
+			attributes.push(Attribute::Color);
+		}
+		attributes
+	}
+
+	fn read(self: Box<Self>, center: Vector<3, f64>, cb: &(dyn Fn(Chunk) + Sync)) -> Result<(), Error> {
+		ordered::try_for_each(
+			&self.chunks,
+			|&(s, l)| {
+				let mut file = BufReader::new(std::fs::File::open(&self.path)?);
+				file.seek(SeekFrom::Start(s))?;
+
+				let mut slice = vec![0; l * self.format.point_length];
+				file.read_exact(&mut slice)?;
+
+				let points = slice
+					.chunks_exact(self.format.point_length)
+					.map(|record| SourcePoint {
+						position: (to_importer(self.format.position(record)) - center).map(|x| x as f32),
+						attributes: self.format.attributes(record),
+					})
+					.collect();
+
+				Ok(Chunk::from_points(points))
+			},
+			|chunk| {
+				cb(chunk);
+				Ok(())
+			},
+		)
+	}
+}
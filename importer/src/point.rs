@@ -1,5 +1,7 @@
 use std::num::NonZeroU32;
 
+use crate::attributes::ATTRIBUTES;
+
 #[derive(Debug)]
 pub struct Point {
 	pub render: project::Point,
@@ -8,6 +10,7 @@ pub struct Point {
 	pub slice: u32,
 	pub height: u32,
 	pub curve: u32,
+	pub attributes: [u32; ATTRIBUTES],
 }
 
 pub struct PointsCollection {
@@ -17,6 +20,7 @@ pub struct PointsCollection {
 	pub height: Vec<u32>,
 	pub curve: Vec<u32>,
 	pub segment: Vec<u32>,
+	pub attributes: [Vec<u32>; ATTRIBUTES],
 }
 
 impl PointsCollection {
@@ -28,6 +32,7 @@ impl PointsCollection {
 			height: Vec::new(),
 			curve: Vec::new(),
 			segment: Vec::new(),
+			attributes: Default::default(),
 		}
 	}
 
@@ -39,6 +44,7 @@ impl PointsCollection {
 			height: Vec::with_capacity(points.len()),
 			curve: Vec::with_capacity(points.len()),
 			segment: Vec::with_capacity(points.len()),
+			attributes: std::array::from_fn(|_| Vec::with_capacity(points.len())),
 		};
 		for point in points {
 			res.render.push(point.render);
@@ -46,15 +52,29 @@ impl PointsCollection {
 			res.height.push(point.height);
 			res.curve.push(point.curve);
 			res.segment.push(point.segment.get());
+			for (values, value) in res.attributes.iter_mut().zip(point.attributes) {
+				values.push(value);
+			}
 		}
 		res
 	}
 
-	pub fn add(&mut self, render: project::Point, slice: u32, height: u32, curve: u32, segment: u32) {
+	pub fn add(
+		&mut self,
+		render: project::Point,
+		slice: u32,
+		height: u32,
+		curve: u32,
+		segment: u32,
+		attributes: [u32; ATTRIBUTES],
+	) {
 		self.render.push(render);
 		self.slice.push(slice);
 		self.height.push(height);
 		self.curve.push(curve);
 		self.segment.push(segment);
+		for (values, value) in self.attributes.iter_mut().zip(attributes) {
+			values.push(value);
+		}
 	}
 }
@@ -1,52 +1,46 @@
 use std::{
 	io::Write,
+	path::Path,
+	sync::Mutex,
 	time::{Duration, Instant},
 };
 
-pub struct Progress<'a> {
-	start: Instant,
-	time: Instant,
-	current: usize,
-	goal: usize,
-	name: &'a str,
+use crate::{Error, Statistics};
+
+/// Receiver for the progress of the import stages.
+///
+/// Stages with a goal report their steps, other stages only start and finish.
+pub trait Observer: Sync {
+	fn start(&self, stage: &str, goal: Option<usize>);
+
+	/// Called at most every 100 milliseconds per stage.
+	fn progress(&self, stage: &str, current: usize, goal: usize, elapsed: Duration);
+
+	fn finish(&self, stage: &str, goal: Option<usize>, elapsed: Duration);
+
+	/// Called once after all stages with the saved statistics.
+	fn summary(&self, _statistics: &Statistics) {}
 }
 
-impl<'a> Progress<'a> {
-	pub fn new(name: &'a str, goal: usize) -> Self {
-		let start = Instant::now();
-		Self::print(name, 0, goal, start);
-		Self {
-			start,
-			time: start,
-			current: 0,
-			goal,
-			name,
-		}
-	}
+/// Observer without any output.
+pub struct Silent;
 
-	pub fn step(&mut self) {
-		self.current += 1;
-		self.maybe_print();
-	}
+impl Observer for Silent {
+	fn start(&self, _stage: &str, _goal: Option<usize>) {}
 
-	fn maybe_print(&mut self) {
-		let now = Instant::now();
-		if now.duration_since(self.time) > Duration::from_millis(100) {
-			self.time = now;
-			Self::print(self.name, self.current, self.goal, self.start);
-		}
-	}
+	fn progress(&self, _stage: &str, _current: usize, _goal: usize, _elapsed: Duration) {}
 
-	#[allow(dead_code)]
-	pub fn step_by(&mut self, amount: usize) {
-		self.current += amount;
-		self.maybe_print();
-	}
+	fn finish(&self, _stage: &str, _goal: Option<usize>, _elapsed: Duration) {}
+}
+
+/// Progress bars in the terminal.
+pub struct Terminal;
 
+impl Terminal {
 	const SUB_STEPS: &'static str = " ▏▎▍▌▋▊▉";
 
-	fn print(name: &str, progress: usize, goal: usize, start: Instant) {
-		let (hours, minutes, seconds) = time(start);
+	fn print(name: &str, progress: usize, goal: usize, elapsed: Duration) {
+		let (hours, minutes, seconds) = time(elapsed);
 
 		let sub_length = Self::SUB_STEPS.chars().count();
 		let mut size = termsize::get().map(|s| s.cols as usize).unwrap_or(80);
@@ -82,16 +76,186 @@ impl<'a> Progress<'a> {
 		);
 		std::io::stdout().flush().unwrap();
 	}
+}
+
+impl Observer for Terminal {
+	fn start(&self, stage: &str, goal: Option<usize>) {
+		match goal {
+			Some(goal) => Self::print(stage, 0, goal, Duration::ZERO),
+			None => {
+				print!(
+					"{}:{: >width$} [--:--:--] ...\r",
+					stage,
+					"",
+					width = 15 - stage.len()
+				);
+				std::io::stdout().flush().unwrap();
+			},
+		}
+	}
+
+	fn progress(&self, stage: &str, current: usize, goal: usize, elapsed: Duration) {
+		Self::print(stage, current, goal, elapsed);
+	}
+
+	fn finish(&self, stage: &str, goal: Option<usize>, elapsed: Duration) {
+		match goal {
+			Some(goal) => {
+				Self::print(stage, goal, goal, elapsed);
+				println!();
+			},
+			None => {
+				let (hours, minutes, seconds) = time(elapsed);
+				println!(
+					"{}:{: >width$} [{:0>2}:{:0>2}:{:0>2}] ...",
+					stage,
+					"",
+					hours,
+					minutes,
+					seconds,
+					width = 15 - stage.len()
+				);
+			},
+		}
+	}
+}
+
+/// One JSON object per line for every event.
+pub struct Json {
+	output: Mutex<Box<dyn Write + Send>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+	Start {
+		stage: &'a str,
+		goal: Option<usize>,
+	},
+	Progress {
+		stage: &'a str,
+		current: usize,
+		goal: usize,
+		elapsed: f32,
+	},
+	Finish {
+		stage: &'a str,
+		goal: Option<usize>,
+		elapsed: f32,
+	},
+	Summary {
+		statistics: &'a Statistics,
+	},
+	Error {
+		message: String,
+	},
+}
+
+impl Json {
+	pub fn stdout() -> Self {
+		Self {
+			output: Mutex::new(Box::new(std::io::stdout())),
+		}
+	}
+
+	pub fn file(path: &Path) -> Result<Self, Error> {
+		let file = std::fs::File::create(path)?;
+		Ok(Self { output: Mutex::new(Box::new(file)) })
+	}
+
+	/// Report an error, which stopped the import.
+	pub fn error(&self, error: &Error) {
+		self.write(Event::Error { message: error.to_string() });
+	}
+
+	fn write(&self, event: Event) {
+		let mut output = self.output.lock().unwrap();
+		// progress is only informative, so failed writes are ignored
+		_ = serde_json::to_writer(&mut *output, &event);
+		_ = writeln!(output);
+		_ = output.flush();
+	}
+}
+
+impl Observer for Json {
+	fn start(&self, stage: &str, goal: Option<usize>) {
+		self.write(Event::Start { stage, goal });
+	}
+
+	fn progress(&self, stage: &str, current: usize, goal: usize, elapsed: Duration) {
+		self.write(Event::Progress {
+			stage,
+			current,
+			goal,
+			elapsed: elapsed.as_secs_f32(),
+		});
+	}
+
+	fn finish(&self, stage: &str, goal: Option<usize>, elapsed: Duration) {
+		self.write(Event::Finish {
+			stage,
+			goal,
+			elapsed: elapsed.as_secs_f32(),
+		});
+	}
+
+	fn summary(&self, statistics: &Statistics) {
+		self.write(Event::Summary { statistics });
+	}
+}
+
+pub struct Progress<'a> {
+	start: Instant,
+	time: Instant,
+	current: usize,
+	goal: usize,
+	name: &'a str,
+	observer: &'a dyn Observer,
+}
+
+impl<'a> Progress<'a> {
+	pub fn new(name: &'a str, goal: usize, observer: &'a dyn Observer) -> Self {
+		let start = Instant::now();
+		observer.start(name, Some(goal));
+		Self {
+			start,
+			time: start,
+			current: 0,
+			goal,
+			name,
+			observer,
+		}
+	}
+
+	pub fn step(&mut self) {
+		self.current += 1;
+		self.maybe_print();
+	}
+
+	fn maybe_print(&mut self) {
+		let now = Instant::now();
+		if now.duration_since(self.time) > Duration::from_millis(100) {
+			self.time = now;
+			self.observer
+				.progress(self.name, self.current, self.goal, self.start.elapsed());
+		}
+	}
+
+	#[allow(dead_code)]
+	pub fn step_by(&mut self, amount: usize) {
+		self.current += amount;
+		self.maybe_print();
+	}
 
 	pub fn finish(self) -> f32 {
-		Self::print(self.name, self.goal, self.goal, self.start);
-		println!();
-		self.start.elapsed().as_secs_f32()
+		let elapsed = self.start.elapsed();
+		self.observer.finish(self.name, Some(self.goal), elapsed);
+		elapsed.as_secs_f32()
 	}
 }
 
-fn time(start: Instant) -> (u64, u64, u64) {
-	let seconds = start.elapsed().as_secs();
+fn time(elapsed: Duration) -> (u64, u64, u64) {
+	let seconds = elapsed.as_secs();
 
 	let minutes = seconds / 60;
 	let hours = minutes / 60;
@@ -104,32 +268,18 @@ fn time(start: Instant) -> (u64, u64, u64) {
 pub struct Stage<'a> {
 	start: Instant,
 	name: &'a str,
+	observer: &'a dyn Observer,
 }
 
 impl<'a> Stage<'a> {
-	pub fn new(name: &'a str) -> Self {
-		print!(
-			"{}:{: >width$} [--:--:--] ...\r",
-			name,
-			"",
-			width = 15 - name.len()
-		);
-		std::io::stdout().flush().unwrap();
-
-		Self { start: Instant::now(), name }
+	pub fn new(name: &'a str, observer: &'a dyn Observer) -> Self {
+		observer.start(name, None);
+		Self { start: Instant::now(), name, observer }
 	}
 
 	pub fn finish(self) -> f32 {
-		let (hours, minutes, seconds) = time(self.start);
-		println!(
-			"{}:{: >width$} [{:0>2}:{:0>2}:{:0>2}] ...",
-			self.name,
-			"",
-			hours,
-			minutes,
-			seconds,
-			width = 15 - self.name.len()
-		);
-		self.start.elapsed().as_secs_f32()
+		let elapsed = self.start.elapsed();
+		self.observer.finish(self.name, None, elapsed);
+		elapsed.as_secs_f32()
 	}
 }
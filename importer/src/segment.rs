@@ -1,23 +1,115 @@
+use std::{collections::BTreeMap, num::NonZeroU32};
+
 use math::{Vector, X, Y, Z};
 use rayon::prelude::*;
 use voronator::delaunator::Point;
 
-#[cfg(feature = "segmentation-svgs")]
-use std::io::Write;
-
 use crate::{
 	cache::{Cache, CacheEntry, CacheIndex},
-	progress::Progress,
-	Settings, Statistics,
+	debug::{SegmentationDebug, SliceDebug},
+	labels::LabelSegmenter,
+	progress::{Observer, Progress},
+	source::SourcePoint,
+	tiled::TiledSegmenter,
+	watershed::ChmWatershed,
+	Error, Settings, Statistics,
 };
 
+/// Algorithm to split the points into trees.
+#[derive(
+	Clone,
+	Copy,
+	Debug,
+	PartialEq,
+	Eq,
+	clap::ValueEnum,
+	serde::Serialize,
+	serde::Deserialize
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Segmentation {
+	/// Voronoi cells around the stems in horizontal slices, best for visible stems
+	Slices,
+	/// Watershed from the treetops in the canopy height model, best for dense canopies
+	ChmWatershed,
+}
+
+/// Segmentation algorithm, which collects the points before splitting them into trees.
+pub trait Segmenter: Send {
+	/// Add a point with the height above the lowest point or the ground.
+	fn add_point(&mut self, point: SourcePoint, height: f32, cache: &mut Cache) -> Result<(), Error>;
+
+	/// Points outside of every tree and segments smaller than `min_segment_size` are added to `unassigned`.
+	fn segments(
+		self: Box<Self>,
+		unassigned: &mut Tiles,
+		statistics: &mut Statistics,
+		cache: &mut Cache,
+		observer: &dyn Observer,
+	) -> Result<Vec<Segment>, Error>;
+}
+
+/// Segmenter for the selected algorithm, or for the labels with `segment_from`.
+///
+/// Only the slices save the `debug` files.
+pub fn new(
+	min: Vector<3, f32>,
+	max: Vector<3, f32>,
+	cache: &mut Cache,
+	settings: &Settings,
+	debug: Option<SegmentationDebug>,
+) -> Box<dyn Segmenter> {
+	if settings.segment_from.is_some() {
+		return Box::new(LabelSegmenter::new(settings));
+	}
+	if let Some(tile_size) = settings.segmenting_tile_size {
+		return Box::new(TiledSegmenter::new(min, max, tile_size, settings, debug));
+	}
+	algorithm(min, max, cache, settings, debug)
+}
+
+/// Segmenter for the selected algorithm in the whole area.
+pub fn algorithm(
+	min: Vector<3, f32>,
+	max: Vector<3, f32>,
+	cache: &mut Cache,
+	settings: &Settings,
+	debug: Option<SegmentationDebug>,
+) -> Box<dyn Segmenter> {
+	match settings.segmentation {
+		Segmentation::Slices => Box::new(SliceSegmenter::new(min, max, cache, settings, debug)),
+		Segmentation::ChmWatershed => Box::new(ChmWatershed::new(min, max, settings)),
+	}
+}
+
 pub struct Segment {
-	data: CacheEntry<Vector<3, f32>>,
+	data: CacheEntry<SourcePoint>,
+	stem: Vector<2, f32>,
+	label: Option<NonZeroU32>,
 }
 
 impl Segment {
-	pub fn points(self) -> Vec<Vector<3, f32>> {
-		self.data.read()
+	pub fn new(data: CacheEntry<SourcePoint>, stem: Vector<2, f32>) -> Self {
+		Self { data, stem, label: None }
+	}
+
+	pub fn with_label(mut self, label: NonZeroU32) -> Self {
+		self.label = Some(label);
+		self
+	}
+
+	/// Label from the source, if the segment is from `segment_from`.
+	pub fn label(&self) -> Option<NonZeroU32> {
+		self.label
+	}
+
+	/// Horizontal position of the stem or the treetop.
+	pub fn stem(&self) -> Vector<2, f32> {
+		self.stem
+	}
+
+	pub fn data(self) -> CacheEntry<SourcePoint> {
+		self.data
 	}
 
 	pub fn length(&self) -> usize {
@@ -25,17 +117,25 @@ impl Segment {
 	}
 }
 
-pub struct Segmenter {
-	slices: Vec<CacheIndex<Vector<3, f32>>>,
+/// Segments from the stem positions in horizontal slices, starting at the lowest slice.
+pub struct SliceSegmenter {
+	slices: Vec<CacheIndex<SourcePoint>>,
 	min: Vector<3, f32>,
 	max: Vector<3, f32>,
 	slice_height: f32,
 	max_distance: f32,
 	min_segment_size: usize,
+	debug: Option<SegmentationDebug>,
 }
 
-impl Segmenter {
-	pub fn new(min: Vector<3, f32>, max: Vector<3, f32>, cache: &mut Cache, settings: &Settings) -> Self {
+impl SliceSegmenter {
+	pub fn new(
+		min: Vector<3, f32>,
+		max: Vector<3, f32>,
+		cache: &mut Cache,
+		settings: &Settings,
+		debug: Option<SegmentationDebug>,
+	) -> Self {
 		let slice_count = ((max[Y] - min[Y]) / settings.segmenting_slice_width) as usize + 1;
 		let slices = (0..slice_count).map(|_| cache.new_entry()).collect();
 		Self {
@@ -45,21 +145,23 @@ impl Segmenter {
 			slice_height: settings.segmenting_slice_width,
 			max_distance: settings.segmenting_max_distance,
 			min_segment_size: settings.min_segment_size,
+			debug,
 		}
 	}
 
-	pub fn add_point(&mut self, point: Vector<3, f32>, cache: &mut Cache) {
-		let slice = ((self.max[Y] - point[Y]) / self.slice_height) as usize;
-		cache.add_value(&self.slices[slice], point);
-	}
-
-	pub fn segments(self, statistics: &mut Statistics, cache: &mut Cache) -> Vec<Segment> {
+	fn split(
+		self,
+		unassigned: &mut Tiles,
+		statistics: &mut Statistics,
+		cache: &mut Cache,
+		observer: &dyn Observer,
+	) -> Result<Vec<Segment>, Error> {
 		let total = self
 			.slices
 			.iter()
 			.map(|slice| cache.size(slice))
 			.sum::<usize>();
-		let mut progress = Progress::new("Segmenting", total);
+		let mut progress = Progress::new("Segmenting", total, observer);
 
 		let min = Point {
 			x: self.min[X] as f64,
@@ -70,13 +172,9 @@ impl Segmenter {
 			y: self.max[Z] as f64,
 		};
 
-		cfg_if::cfg_if! {
-			if #[cfg(feature = "segmentation-svgs")] {
-				let size = self.max - self.min;
-				_ = std::fs::remove_dir_all("./svg");
-				std::fs::create_dir_all("./svg").unwrap();
-			}
-		}
+		// the first error while reading a slice or saving the debug files, the slices continue so the centroids still arrive
+		let slice_error = std::sync::Mutex::new(None);
+		let first_error = &slice_error;
 
 		let (sender, reciever) = crossbeam::channel::bounded(rayon::current_num_threads());
 
@@ -99,32 +197,21 @@ impl Segmenter {
 					.enumerate()
 					.par_bridge()
 					// .into_par_iter()
-					.for_each(|(_index, (c_reciever, slice, c_sender))| {
-						cfg_if::cfg_if! {
-							if #[cfg(feature = "segmentation-svgs")] {
-								let mut svg = std::fs::File::create(format!("./svg/test_{}.svg", _index)).unwrap();
-								svg.write_all(
-									format!(
-										"<svg viewbox=\"0 0 {} {}\" xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" >\n",
-										size[X] * 10.0,
-										size[Z] * 10.0,
-										size[X] * 10.0,
-										size[Z] * 10.0
-									)
-									.as_bytes(),
-								)
-								.unwrap();
-							}
-						}
-						let slice = slice.read();
+					.for_each(|(index, (c_reciever, slice, c_sender))| {
+						let slice = slice.read().unwrap_or_else(|err| {
+							first_error.lock().unwrap().get_or_insert(err);
+							Vec::new()
+						});
 						let tree_set = TreeSet::new(&slice, self.max_distance);
+						let hulls = match &self.debug {
+							Some(_) => tree_set
+								.trees
+								.iter()
+								.map(|tree| tree.points.clone())
+								.collect(),
+							None => Vec::new(),
+						};
 
-						#[cfg(feature = "segmentation-svgs")]
-						for tree in &tree_set.trees {
-							tree.save_svg(&mut svg, self.min, true);
-						}
-
-						// println!("pre: {} {:?}", index, std::time::Instant::now());
 						let centroids = c_reciever.recv().unwrap();
 						let centroids = tree_set.tree_positions(centroids, self.max_distance);
 						let points = centroids
@@ -134,19 +221,10 @@ impl Segmenter {
 								y: centroid.center[Y] as f64,
 							})
 							.collect::<Vec<_>>();
-
-						#[cfg(feature = "segmentation-svgs")]
-						for centroid in &centroids {
-							svg.write_all(
-								format!(
-									"  <circle cx=\"{}\" cy=\"{}\" r=\"10\" />\n",
-									(centroid.center[X] - self.min[X]) * 10.0,
-									(centroid.center[Y] - self.min[Z]) * 10.0
-								)
-								.as_bytes(),
-							)
-							.unwrap();
-						}
+						let carried = centroids
+							.iter()
+							.map(|centroid| (centroid.center, centroid.carried))
+							.collect::<Vec<_>>();
 						c_sender.send(centroids).unwrap();
 
 						let vor = voronator::VoronoiDiagram::new(&min, &max, &points).unwrap();
@@ -165,57 +243,161 @@ impl Segmenter {
 							.map(|(index, tree)| (index, tree, Vec::new()))
 							.collect::<Vec<_>>();
 
-						cfg_if::cfg_if! {
-							if #[cfg(feature = "segmentation-svgs")] {
-								for (_, tree, _) in &trees {
-									tree.save_svg(&mut svg, self.min, false);
-								}
-								svg.write_all(b"</svg>").unwrap();
-							}
-						}
-
+						let mut grid = CellGrid::new(&trees, &min, &max);
+						let mut outside = Vec::new();
 						for p in slice {
-							let Some((idx, _)) = trees
-								.iter_mut()
-								.enumerate()
-								.find(|(_, (_, tree, _))| tree.contains(Vector::new([p[X], p[Z]]), 0.1))
-							else {
+							let Some(idx) = grid.find(&trees, Vector::new([p.position[X], p.position[Z]])) else {
+								outside.push(p);
 								continue;
 							};
 							trees[idx].2.push(p);
-							// hope next point is in the same segment
-							trees.swap(0, idx);
 						}
-						sender.send(trees).unwrap();
+
+						if let Some(debug) = &self.debug {
+							let max_height = self.max[Y] - self.min[Y] - index as f32 * self.slice_height;
+							let slice = SliceDebug {
+								index,
+								min_height: max_height - self.slice_height,
+								max_height,
+								hulls,
+								centroids: carried,
+								cells: trees
+									.iter()
+									.map(|(_, tree, points)| (tree.points.clone(), points.len()))
+									.collect(),
+							};
+							if let Err(err) = debug.save(&slice) {
+								first_error.lock().unwrap().get_or_insert(err);
+							}
+						}
+						sender.send((trees, outside)).unwrap();
 					});
 				drop(sender);
 			},
 			|| {
 				let mut segments = Vec::new();
-				for trees in reciever {
+				let mut reciever = reciever.into_iter();
+				let result = reciever.by_ref().try_for_each(|(trees, outside)| {
+					progress.step_by(outside.len());
+					for point in outside {
+						unassigned.add_point(point, cache)?;
+					}
 					for (id, _, points) in trees {
 						let l = points.len();
 						if id >= segments.len() {
 							segments.resize_with(id + 1, || cache.new_entry());
 						}
-						cache.add_chunk(&segments[id], points);
+						cache.add_chunk(&segments[id], points)?;
 						progress.step_by(l);
 					}
-				}
-				drop(c_reciever);
-				segments
+					Result::<(), Error>::Ok(())
+				});
+				// after an error the slices are still received, so they can finish
+				reciever.for_each(drop);
+				result.map(|()| segments)
 			},
 		);
+		let segments = segments?;
+		// centroids after the lowest slice, the index is the segment
+		let centroids = c_reciever.recv().unwrap_or_default();
 
 		statistics.times.segment = progress.finish();
 
-		let mut segments = segments
+		let segments = segments
 			.into_iter()
-			.map(|entry| Segment { data: cache.read(entry) })
-			.filter(|entry| entry.length() >= self.min_segment_size)
+			.enumerate()
+			.map(|(index, entry)| {
+				let stem = centroids
+					.get(index)
+					.map(|centroid| centroid.center)
+					.unwrap_or_default();
+				Segment::new(cache.read(entry), stem)
+			})
 			.collect::<Vec<_>>();
-		segments.sort_by(|a, b| b.data.active().cmp(&a.data.active()));
-		segments
+		if let Some(err) = slice_error.into_inner().unwrap() {
+			return Err(err);
+		}
+		remove_small(segments, self.min_segment_size, unassigned, cache)
+	}
+}
+
+impl Segmenter for SliceSegmenter {
+	fn add_point(&mut self, point: SourcePoint, height: f32, cache: &mut Cache) -> Result<(), Error> {
+		let slice = ((self.max[Y] - self.min[Y] - height) / self.slice_height).max(0.0) as usize;
+		let slice = slice.min(self.slices.len() - 1);
+		cache.add_value(&self.slices[slice], point)
+	}
+
+	fn segments(
+		self: Box<Self>,
+		unassigned: &mut Tiles,
+		statistics: &mut Statistics,
+		cache: &mut Cache,
+		observer: &dyn Observer,
+	) -> Result<Vec<Segment>, Error> {
+		(*self).split(unassigned, statistics, cache, observer)
+	}
+}
+
+/// Keep the segments with at least `min_size` points, the points of smaller segments are unassigned.
+pub fn remove_small(
+	segments: Vec<Segment>,
+	min_size: usize,
+	unassigned: &mut Tiles,
+	cache: &mut Cache,
+) -> Result<Vec<Segment>, Error> {
+	let mut kept = Vec::with_capacity(segments.len());
+	for segment in segments {
+		if segment.length() >= min_size {
+			kept.push(segment);
+		} else {
+			for point in segment.data.read()? {
+				unassigned.add_point(point, cache)?;
+			}
+		}
+	}
+	Ok(kept)
+}
+
+/// Horizontal size of the tiles in meters.
+const TILE_SIZE: f32 = 10.0;
+
+/// Points without a tree grouped into square tiles.
+///
+/// Each tile is calculated like a segment, so the neighbors stay local and the tiles are
+/// calculated in parallel.
+pub struct Tiles {
+	min: Vector<3, f32>,
+	tiles: BTreeMap<(usize, usize), CacheIndex<SourcePoint>>,
+	length: usize,
+}
+
+impl Tiles {
+	pub fn new(min: Vector<3, f32>) -> Self {
+		Self { min, tiles: BTreeMap::new(), length: 0 }
+	}
+
+	pub fn add_point(&mut self, point: SourcePoint, cache: &mut Cache) -> Result<(), Error> {
+		let key = (
+			((point.position[X] - self.min[X]) / TILE_SIZE).max(0.0) as usize,
+			((point.position[Z] - self.min[Z]) / TILE_SIZE).max(0.0) as usize,
+		);
+		let tile = self.tiles.entry(key).or_insert_with(|| cache.new_entry());
+		cache.add_value(tile, point)?;
+		self.length += 1;
+		Ok(())
+	}
+
+	pub fn length(&self) -> usize {
+		self.length
+	}
+
+	/// Points of every tile, ordered by the tile position.
+	pub fn entries(self, cache: &mut Cache) -> Vec<CacheEntry<SourcePoint>> {
+		self.tiles
+			.into_values()
+			.map(|tile| cache.read(tile))
+			.collect()
 	}
 }
 
@@ -384,50 +566,95 @@ impl Tree {
 	// 	}
 	// 	res
 	// }
+}
 
-	#[cfg(feature = "segmentation-svgs")]
-	pub fn save_svg(&self, file: &mut std::fs::File, min: Vector<3, f32>, fill: bool) {
-		file.write_all(b"  <polygon points=\"").unwrap();
-		for &point in &self.points {
-			file.write_all(
-				format!(
-					"{},{} ",
-					(point[X] - min[X]) * 10.0,
-					(point[Y] - min[Z]) * 10.0
-				)
-				.as_bytes(),
-			)
-			.unwrap();
-		}
-		if fill {
-			file.write_all(
-				format!(
-					"\" fill=\"rgb({}, {}, {})\"/>\n",
-					rand::random::<u8>(),
-					rand::random::<u8>(),
-					rand::random::<u8>(),
-				)
-				.as_bytes(),
-			)
-			.unwrap();
-		} else {
-			file.write_all("\" stroke=\"black\" fill=\"none\" />\n".as_bytes())
-				.unwrap();
+/// Uniform grid over the bounding boxes of the Voronoi cells, to find the cells for a point
+/// without testing every cell.
+///
+/// Points on the border of multiple cells go to the cell used most recently, like moving the
+/// used cell to the front of the list and searching the list from the front.
+struct CellGrid {
+	min: Vector<2, f32>,
+	cell_size: f32,
+	width: usize,
+	depth: usize,
+	cells: Vec<Vec<usize>>,
+	/// Cell at each place of the list.
+	order: Vec<usize>,
+	/// Place of each cell in the list.
+	places: Vec<usize>,
+}
+
+impl CellGrid {
+	fn new(trees: &[(usize, Tree, Vec<SourcePoint>)], min: &Point, max: &Point) -> Self {
+		let min = Vector::new([min.x as f32, min.y as f32]);
+		let size = Vector::new([max.x as f32, max.y as f32]) - min;
+		// about one Voronoi cell per grid cell
+		let cell_size = (size[X] * size[Y] / trees.len().max(1) as f32)
+			.sqrt()
+			.max(0.1);
+		let width = (size[X] / cell_size) as usize + 1;
+		let depth = (size[Y] / cell_size) as usize + 1;
+
+		let mut grid = Self {
+			min,
+			cell_size,
+			width,
+			depth,
+			cells: vec![Vec::new(); width * depth],
+			order: (0..trees.len()).collect(),
+			places: (0..trees.len()).collect(),
+		};
+		for (index, (_, tree, _)) in trees.iter().enumerate() {
+			if tree.points.is_empty() {
+				continue;
+			}
+			let (min_x, min_z) = grid.cell(tree.min);
+			let (max_x, max_z) = grid.cell(tree.max);
+			for z in min_z..=max_z {
+				for x in min_x..=max_x {
+					grid.cells[x + z * width].push(index);
+				}
+			}
 		}
+		grid
+	}
+
+	fn cell(&self, position: Vector<2, f32>) -> (usize, usize) {
+		let x = ((position[X] - self.min[X]) / self.cell_size).max(0.0) as usize;
+		let z = ((position[Y] - self.min[Y]) / self.cell_size).max(0.0) as usize;
+		(x.min(self.width - 1), z.min(self.depth - 1))
+	}
+
+	/// Cell containing the position and move it to the front of the list.
+	fn find(&mut self, trees: &[(usize, Tree, Vec<SourcePoint>)], position: Vector<2, f32>) -> Option<usize> {
+		let (x, z) = self.cell(position);
+		let idx = self.cells[x + z * self.width]
+			.iter()
+			.copied()
+			.filter(|&idx| trees[idx].1.contains(position, 0.1))
+			.min_by_key(|&idx| self.places[idx])?;
+		let first = self.order[0];
+		self.order.swap(0, self.places[idx]);
+		self.places[first] = self.places[idx];
+		self.places[idx] = 0;
+		Some(idx)
 	}
 }
 
 #[derive(Clone, Copy)]
 struct Centroid {
 	center: Vector<2, f32>,
+	/// Kept from the previous slice without a match in the current slice.
+	carried: bool,
 }
 
 impl TreeSet {
-	pub fn new(points: &[Vector<3, f32>], max_distance: f32) -> Self {
+	pub fn new(points: &[SourcePoint], max_distance: f32) -> Self {
 		let mut trees = Vec::<Tree>::new();
-		'iter_points: for &point in points {
+		'iter_points: for point in points {
 			let mut near = Vec::new();
-			let p = Vector::new([point[X], point[Z]]);
+			let p = Vector::new([point.position[X], point.position[Z]]);
 			for (i, tree) in trees.iter().enumerate() {
 				let dist = tree.distance(p, max_distance);
 				if dist <= 0.0 {
@@ -496,13 +723,13 @@ impl TreeSet {
 			}
 			if let Some(idx) = nearest {
 				let c = centroids.swap_remove(idx);
-				res.push(Centroid { center: c });
+				res.push(Centroid { center: c, carried: false });
 			} else {
-				res.push(center)
+				res.push(Centroid { carried: true, ..center })
 			}
 		}
 		for c in centroids {
-			res.push(Centroid { center: c });
+			res.push(Centroid { center: c, carried: false });
 		}
 		res
 	}
@@ -526,3 +753,81 @@ fn centroid(points: &[Vector<2, f32>]) -> (Vector<2, f32>, f32) {
 
 	(a + center / area, area)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Pseudo random value between 0 and 1.
+	fn random(state: &mut u32) -> f32 {
+		*state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+		(*state >> 8) as f32 / (1 << 24) as f32
+	}
+
+	/// Voronoi cells around pseudo random centers.
+	fn cells(count: usize, min: &Point, max: &Point) -> Vec<(usize, Tree, Vec<SourcePoint>)> {
+		let mut state = 12345;
+		let centers = (0..count)
+			.map(|_| Point {
+				x: min.x + random(&mut state) as f64 * (max.x - min.x),
+				y: min.y + random(&mut state) as f64 * (max.y - min.y),
+			})
+			.collect::<Vec<_>>();
+		voronator::VoronoiDiagram::new(min, max, &centers)
+			.unwrap()
+			.cells()
+			.iter()
+			.map(|cell| {
+				let points = cell
+					.points()
+					.iter()
+					.map(|p| Vector::new([p.x as f32, p.y as f32]))
+					.collect();
+				Tree::from_points(points, 0.1)
+			})
+			.enumerate()
+			.map(|(index, tree)| (index, tree, Vec::new()))
+			.collect()
+	}
+
+	#[test]
+	fn grid_matches_linear_search() {
+		let size = 20.0;
+		let (min, max) = (Point { x: 0.0, y: 0.0 }, Point { x: size, y: size });
+		let trees = cells(50, &min, &max);
+		let mut grid = CellGrid::new(&trees, &min, &max);
+
+		// the lookup before the grid, search all cells and swap the found cell to the front
+		let mut linear = trees
+			.iter()
+			.map(|(index, tree, _)| (*index, tree))
+			.collect::<Vec<_>>();
+
+		let mut state = 54321;
+		let mut borders = 0;
+		for _ in 0..20_000 {
+			let position = Vector::new([
+				random(&mut state) * (size as f32 + 1.0) - 0.5,
+				random(&mut state) * (size as f32 + 1.0) - 0.5,
+			]);
+			let expected = linear
+				.iter()
+				.position(|(_, tree)| tree.contains(position, 0.1))
+				.map(|idx| {
+					let index = linear[idx].0;
+					linear.swap(0, idx);
+					index
+				});
+			if trees
+				.iter()
+				.filter(|(_, tree, _)| tree.contains(position, 0.1))
+				.count() > 1
+			{
+				borders += 1;
+			}
+			assert_eq!(grid.find(&trees, position), expected);
+		}
+		// the points on the borders are the ones depending on the order
+		assert!(borders > 0);
+	}
+}
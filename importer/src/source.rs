@@ -0,0 +1,251 @@
+use std::path::{Path, PathBuf};
+
+use math::{Vector, X};
+
+use crate::{
+	attributes::{Attribute, Attributes},
+	e57::E57,
+	las::Las,
+	laz::Laz,
+	xyz::Xyz,
+	Error, TextFormat,
+};
+
+/// Points per chunk, same as the default chunk size for compressed files.
+pub const CHUNK_SIZE: usize = 50_000;
+
+/// File extensions with a matching source.
+pub const EXTENSIONS: [&str; 7] = ["las", "laz", "ply", "xyz", "txt", "csv", "e57"];
+
+/// Point cloud input for the importer.
+///
+/// Positions are returned in the importer coordinate system (x, z, -y) relative to the
+/// center passed to `read`.
+pub trait Source: Send {
+	fn bounds(&self) -> Bounds;
+
+	fn total(&self) -> usize;
+
+	/// Attributes with values in the source, all others are left at default.
+	fn attributes(&self) -> Vec<Attribute> {
+		Vec::new()
+	}
+
+	/// Coordinate reference system of the source coordinates.
+	fn crs(&self) -> Option<String> {
+		None
+	}
+
+	/// Read all points, `cb` is called with the chunks in the same order for every read.
+	fn read(self: Box<Self>, center: Vector<3, f64>, cb: &(dyn Fn(Chunk) + Sync)) -> Result<(), Error>;
+}
+
+/// Expand directories and glob patterns into the input files.
+pub fn inputs(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+	let mut inputs = Vec::new();
+	for path in paths {
+		if path.is_dir() {
+			let mut files = std::fs::read_dir(path)?
+				.map(|entry| entry.map(|entry| entry.path()))
+				.collect::<Result<Vec<_>, _>>()?;
+			files.retain(|file| file.is_file() && is_supported(file));
+			files.sort();
+			inputs.extend(files);
+		} else if path.exists() {
+			inputs.push(path.clone());
+		} else {
+			let pattern = path.to_str().ok_or(Error::NoInputFile)?;
+			let mut files = glob::glob(pattern)?
+				.filter_map(|entry| entry.ok())
+				.filter(|file| file.is_file())
+				.collect::<Vec<_>>();
+			files.sort();
+			inputs.extend(files);
+		}
+	}
+	if inputs.is_empty() {
+		return Err(Error::NoInputFile);
+	}
+	Ok(inputs)
+}
+
+fn extension(path: &Path) -> String {
+	path.extension()
+		.and_then(|extension| extension.to_str())
+		.map(|extension| extension.to_lowercase())
+		.unwrap_or_default()
+}
+
+pub fn is_supported(path: &Path) -> bool {
+	EXTENSIONS.contains(&extension(path).as_str())
+}
+
+/// Open all inputs as a single source with the combined bounds.
+///
+/// With a `label`, the points have the tree labels from the field with the name.
+pub fn open(paths: &[PathBuf], text: &TextFormat, label: Option<&str>) -> Result<Box<dyn Source>, Error> {
+	let mut sources = paths
+		.iter()
+		.map(|path| open_file(path, text, label))
+		.collect::<Result<Vec<_>, _>>()?;
+	if sources.len() == 1 {
+		return Ok(sources.pop().unwrap());
+	}
+	// the project has a single georeference, so the inputs must not mix coordinate systems
+	let mut crs = sources.iter().filter_map(|source| source.crs());
+	if let Some(first) = crs.next() {
+		if crs.any(|crs| crs != first) {
+			return Err(Error::DifferentCrs);
+		}
+	}
+	Ok(Box::new(Multiple { sources }))
+}
+
+/// Open the matching source for the file extension.
+fn open_file(path: &Path, text: &TextFormat, label: Option<&str>) -> Result<Box<dyn Source>, Error> {
+	let extension = extension(path);
+	// only las and laz files have fields for the labels
+	if let (Some(label), false) = (label, matches!(extension.as_str(), "las" | "laz")) {
+		return Err(Error::MissingLabel(label.into()));
+	}
+
+	Ok(match extension.as_str() {
+		"las" | "laz" => {
+			let header = crate::laz::Header::new(std::fs::File::open(path)?)?;
+			if header.is_compressed() {
+				Box::new(Laz::new(path, label)?)
+			} else {
+				Box::new(Las::new(path, label)?)
+			}
+		},
+		"ply" => crate::ply::open(path)?,
+		"xyz" | "txt" => Box::new(Xyz::new(path, text, None)?),
+		"csv" => Box::new(Xyz::new(path, text, Some(','))?),
+		"e57" => Box::new(E57::new(path)?),
+		_ => return Err(Error::UnsupportedFormat(extension)),
+	})
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SourcePoint {
+	pub position: Vector<3, f32>,
+	pub attributes: Attributes,
+}
+
+/// Multiple sources read one after another, like adjacent tiles.
+struct Multiple {
+	sources: Vec<Box<dyn Source>>,
+}
+
+impl Source for Multiple {
+	fn bounds(&self) -> Bounds {
+		let mut bounds = Bounds::new();
+		for source in &self.sources {
+			bounds.merge(source.bounds());
+		}
+		bounds
+	}
+
+	fn total(&self) -> usize {
+		self.sources.iter().map(|source| source.total()).sum()
+	}
+
+	fn attributes(&self) -> Vec<Attribute> {
+		let mut attributes = Vec::new();
+		for attribute in self.sources.iter().flat_map(|source| source.attributes()) {
+			if !attributes.contains(&attribute) {
+				attributes.push(attribute);
+			}
+		}
+		attributes
+	}
+
+	/// Sources with a coordinate reference system have the same one, checked in `open`.
+	fn crs(&self) -> Option<String> {
+		self.sources.iter().find_map(|source| source.crs())
+	}
+
+	fn read(self: Box<Self>, center: Vector<3, f64>, cb: &(dyn Fn(Chunk) + Sync)) -> Result<(), Error> {
+		for source in self.sources {
+			source.read(center, cb)?;
+		}
+		Ok(())
+	}
+}
+
+pub struct Chunk {
+	points: Vec<SourcePoint>,
+}
+
+impl Chunk {
+	pub fn from_points(points: Vec<SourcePoint>) -> Self {
+		Self { points }
+	}
+
+	pub fn length(&self) -> usize {
+		self.points.len()
+	}
+}
+
+impl IntoIterator for Chunk {
+	type Item = SourcePoint;
+	type IntoIter = std::vec::IntoIter<SourcePoint>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.points.into_iter()
+	}
+}
+
+/// Bounds of the source positions, used to center the points.
+#[derive(Clone, Copy)]
+pub struct Bounds {
+	pub min: Vector<3, f64>,
+	pub max: Vector<3, f64>,
+}
+
+impl Bounds {
+	pub fn new() -> Self {
+		Self {
+			min: Vector::new([f64::MAX, f64::MAX, f64::MAX]),
+			max: Vector::new([f64::MIN, f64::MIN, f64::MIN]),
+		}
+	}
+
+	/// Bounds from z-up source coordinates.
+	pub fn from_source(min: Vector<3, f64>, max: Vector<3, f64>) -> Self {
+		let (min, max) = (to_importer(min), to_importer(max));
+		Self { min: min.min(max), max: min.max(max) }
+	}
+
+	pub fn insert(&mut self, position: Vector<3, f64>) {
+		self.min = self.min.min(position);
+		self.max = self.max.max(position);
+	}
+
+	pub fn merge(&mut self, other: Self) {
+		self.min = self.min.min(other.min);
+		self.max = self.max.max(other.max);
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.min[X] > self.max[X]
+	}
+
+	pub fn center(&self) -> Vector<3, f64> {
+		(self.min + self.max) / 2.0
+	}
+
+	/// Minimum and maximum relative to the center.
+	pub fn local(&self) -> (Vector<3, f32>, Vector<3, f32>) {
+		let center = self.center();
+		(
+			(self.min - center).map(|x| x as f32),
+			(self.max - center).map(|x| x as f32),
+		)
+	}
+}
+
+/// Convert z-up source coordinates into the y-up importer coordinates.
+pub fn to_importer(position: Vector<3, f64>) -> Vector<3, f64> {
+	project::Axes::ZUp.to_project(position)
+}
@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+
+use math::{Vector, X, Y, Z};
+
+use crate::{
+	cache::{Cache, CacheIndex},
+	debug::SegmentationDebug,
+	progress::{Observer, Progress, Silent},
+	segment::{self, remove_small, Segment, Segmentation, Segmenter, Tiles},
+	source::SourcePoint,
+	Error, Settings, Statistics,
+};
+
+/// Segments square tiles one after another, so the memory depends on the tile size instead of the area.
+///
+/// Every tile also gets the points in the overlap around it, so trees crossing the tile edges are
+/// complete. A point belongs to the tile it is in and a tree to the tile of its stem. Trees with the
+/// stem in a neighboring tile are stitched to the nearest tree of that tile.
+///
+/// The points wait in the cache until all are added, then the segmenter of each tile is created and
+/// dropped before the next tile, only the trees inside the tile are kept.
+pub struct TiledSegmenter {
+	min: Vector<3, f32>,
+	max: Vector<3, f32>,
+	tile_size: f32,
+	overlap: f32,
+	columns: usize,
+	rows: usize,
+	/// Points inside each tile and its overlap, with their height.
+	tiles: BTreeMap<(usize, usize), CacheIndex<(SourcePoint, f32)>>,
+	length: usize,
+	stitch_distance: f32,
+	min_segment_size: usize,
+	settings: Settings,
+	debug: Option<SegmentationDebug>,
+}
+
+impl TiledSegmenter {
+	pub fn new(
+		min: Vector<3, f32>,
+		max: Vector<3, f32>,
+		tile_size: f32,
+		settings: &Settings,
+		debug: Option<SegmentationDebug>,
+	) -> Self {
+		let stitch_distance = match settings.segmentation {
+			Segmentation::Slices => settings.segmenting_max_distance * 2.0,
+			Segmentation::ChmWatershed => settings.chm_treetop_distance,
+		};
+		Self {
+			min,
+			max,
+			tile_size,
+			overlap: settings.segmenting_tile_overlap,
+			columns: ((max[X] - min[X]) / tile_size) as usize + 1,
+			rows: ((max[Z] - min[Z]) / tile_size) as usize + 1,
+			tiles: BTreeMap::new(),
+			length: 0,
+			stitch_distance,
+			min_segment_size: settings.min_segment_size,
+			// small parts of trees are only removed after stitching
+			settings: Settings { min_segment_size: 0, ..settings.clone() },
+			debug,
+		}
+	}
+
+	/// Tile containing the horizontal position.
+	fn tile(&self, position: Vector<2, f32>) -> (usize, usize) {
+		let column = ((position[X] - self.min[X]) / self.tile_size).max(0.0) as usize;
+		let row = ((position[Y] - self.min[Z]) / self.tile_size).max(0.0) as usize;
+		(column.min(self.columns - 1), row.min(self.rows - 1))
+	}
+
+	/// Range of the tiles with the coordinate inside the tile or its overlap.
+	fn range(&self, value: f32, min: f32, count: usize) -> std::ops::RangeInclusive<usize> {
+		let start = ((value - min - self.overlap) / self.tile_size).max(0.0) as usize;
+		let end = ((value - min + self.overlap) / self.tile_size).max(0.0) as usize;
+		start.min(count - 1)..=end.min(count - 1)
+	}
+
+	/// Segmenter for the tile and its overlap.
+	fn segmenter(&self, (column, row): (usize, usize), cache: &mut Cache) -> Box<dyn Segmenter> {
+		let offset = Vector::new([column as f32, 0.0, row as f32]) * self.tile_size;
+		let overlap = Vector::new([self.overlap, 0.0, self.overlap]);
+		let min = self.min + offset - overlap;
+		let max = Vector::new([
+			min[X] + self.tile_size + 2.0 * self.overlap,
+			self.max[Y],
+			min[Z] + self.tile_size + 2.0 * self.overlap,
+		]);
+		let debug = self.debug.as_ref().map(|debug| debug.for_tile(column, row));
+		segment::algorithm(min, max, cache, &self.settings, debug)
+	}
+
+	fn split(
+		mut self,
+		unassigned: &mut Tiles,
+		statistics: &mut Statistics,
+		cache: &mut Cache,
+		observer: &dyn Observer,
+	) -> Result<Vec<Segment>, Error> {
+		let mut progress = Progress::new("Segmenting", self.length, observer);
+
+		let horizontal = |position: Vector<3, f32>| Vector::new([position[X], position[Z]]);
+
+		// (tile of the stem, stem, points inside the current tile)
+		let mut owned = Vec::<((usize, usize), Vector<2, f32>, CacheIndex<SourcePoint>)>::new();
+		let mut foreign = Vec::new();
+		for (key, tile) in std::mem::take(&mut self.tiles) {
+			let mut segmenter = self.segmenter(key, cache);
+			for (point, height) in cache.read(tile).read()? {
+				segmenter.add_point(point, height, cache)?;
+			}
+			let mut local = Tiles::new(self.min);
+			for segment in segmenter.segments(&mut local, statistics, cache, &Silent)? {
+				let stem = segment.stem();
+				let points = segment
+					.data()
+					.read()?
+					.into_iter()
+					.filter(|point| self.tile(horizontal(point.position)) == key)
+					.collect::<Vec<_>>();
+				if points.is_empty() {
+					continue;
+				}
+				progress.step_by(points.len());
+				let index = cache.new_entry();
+				cache.add_chunk(&index, points)?;
+				let owner = self.tile(stem);
+				if owner == key {
+					owned.push((owner, stem, index));
+				} else {
+					foreign.push((owner, stem, index));
+				}
+			}
+			for entry in local.entries(cache) {
+				for point in entry.read()? {
+					if self.tile(horizontal(point.position)) == key {
+						unassigned.add_point(point, cache)?;
+						progress.step();
+					}
+				}
+			}
+		}
+
+		// trees without a match in the tile of their stem are kept, if another tile found them too
+		let mut trees = owned;
+		for (owner, stem, index) in foreign {
+			let nearest = trees
+				.iter()
+				.enumerate()
+				.filter(|(_, (tile, _, _))| *tile == owner)
+				.map(|(position, (_, other, _))| (position, stem.distance(*other)))
+				.filter(|&(_, distance)| distance <= self.stitch_distance)
+				.min_by(|a, b| a.1.total_cmp(&b.1));
+			match nearest {
+				Some((position, _)) => {
+					let points = cache.read(index).read()?;
+					cache.add_chunk(&trees[position].2, points)?;
+				},
+				None => trees.push((owner, stem, index)),
+			}
+		}
+
+		statistics.times.segment = progress.finish();
+
+		let segments = trees
+			.into_iter()
+			.map(|(_, stem, index)| Segment::new(cache.read(index), stem))
+			.collect();
+		remove_small(segments, self.min_segment_size, unassigned, cache)
+	}
+}
+
+impl Segmenter for TiledSegmenter {
+	fn add_point(&mut self, point: SourcePoint, height: f32, cache: &mut Cache) -> Result<(), Error> {
+		for row in self.range(point.position[Z], self.min[Z], self.rows) {
+			for column in self.range(point.position[X], self.min[X], self.columns) {
+				let tile = self
+					.tiles
+					.entry((column, row))
+					.or_insert_with(|| cache.new_entry());
+				cache.add_value(tile, (point, height))?;
+			}
+		}
+		self.length += 1;
+		Ok(())
+	}
+
+	fn segments(
+		self: Box<Self>,
+		unassigned: &mut Tiles,
+		statistics: &mut Statistics,
+		cache: &mut Cache,
+		observer: &dyn Observer,
+	) -> Result<Vec<Segment>, Error> {
+		(*self).split(unassigned, statistics, cache, observer)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::progress::Silent;
+
+	/// A stem 1 meter before the edge between two tiles and a cone shaped crown reaching 2 meters
+	/// into the next tile.
+	fn tree() -> Vec<SourcePoint> {
+		let mut positions = Vec::new();
+		for step in 0..100 {
+			let height = step as f32 * 0.1;
+			for index in 0..8 {
+				let angle = index as f32 / 8.0 * std::f32::consts::TAU;
+				positions.push(Vector::new([
+					9.0 + 0.15 * angle.cos(),
+					height,
+					5.0 + 0.15 * angle.sin(),
+				]));
+			}
+		}
+		for step in 0..30 {
+			let height = 4.0 + step as f32 * 0.2;
+			let radius = 3.0 * (10.0 - height) / 6.0;
+			let count = (radius * 20.0) as usize + 1;
+			for index in 0..count {
+				let angle = index as f32 / count as f32 * std::f32::consts::TAU;
+				positions.push(Vector::new([
+					9.0 + radius * angle.cos(),
+					height,
+					5.0 + radius * angle.sin(),
+				]));
+			}
+		}
+		positions
+			.into_iter()
+			.map(|position| SourcePoint { position, attributes: Default::default() })
+			.collect()
+	}
+
+	fn stitched(segmentation: Segmentation) {
+		let points = tree();
+		let settings = Settings {
+			segmentation,
+			segmenting_tile_size: Some(10.0),
+			segmenting_tile_overlap: 5.0,
+			..Settings::default()
+		};
+		let (min, max) = (Vector::new([0.0; 3]), Vector::new([20.0, 10.0, 10.0]));
+
+		let pool = rayon::ThreadPoolBuilder::new()
+			.num_threads(2)
+			.build()
+			.unwrap();
+		let (segments, lengths, unassigned) = pool.install(|| {
+			let mut cache = Cache::new(usize::MAX, None);
+			let mut segmenter = segment::new(min, max, &mut cache, &settings, None);
+			for &point in &points {
+				segmenter
+					.add_point(point, point.position[Y], &mut cache)
+					.unwrap();
+			}
+			let mut unassigned = Tiles::new(min);
+			let segments = segmenter
+				.segments(
+					&mut unassigned,
+					&mut Statistics::default(),
+					&mut cache,
+					&Silent,
+				)
+				.unwrap();
+			let lengths = segments.iter().map(Segment::length).collect::<Vec<_>>();
+			(segments.len(), lengths, unassigned.length())
+		});
+		assert_eq!(segments, 1, "segment lengths {:?}", lengths);
+		assert_eq!(lengths[0], points.len());
+		assert_eq!(unassigned, 0);
+	}
+
+	#[test]
+	fn stitch_slices() {
+		stitched(Segmentation::Slices);
+	}
+
+	#[test]
+	fn stitch_chm_watershed() {
+		stitched(Segmentation::ChmWatershed);
+	}
+}
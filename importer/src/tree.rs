@@ -1,8 +1,6 @@
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 use std::num::NonZeroU32;
-use std::sync::Mutex;
 
-use crossbeam::atomic::AtomicCell;
 use math::Vector;
 use math::{X, Z};
 use project::{IndexData, IndexNode, Project, MAX_LEAF_SIZE};
@@ -10,14 +8,14 @@ use rayon::prelude::*;
 
 use crate::cache::{Cache, CacheEntry, CacheIndex};
 use crate::point::{Point, PointsCollection};
-use crate::progress::Progress;
-use crate::{level_of_detail, Settings, Statistics, Writer};
+use crate::progress::{Observer, Progress};
+use crate::{level_of_detail, ordered, Error, Settings, Statistics, Writer};
 
 #[derive(Debug)]
 pub enum Data {
 	Leaf {
 		size: usize,
-		segments: HashSet<NonZeroU32>,
+		segments: BTreeSet<NonZeroU32>,
 		index: CacheIndex<Point>,
 	},
 	Branch {
@@ -47,20 +45,20 @@ impl Node {
 			size,
 			data: Data::Leaf {
 				size: 0,
-				segments: HashSet::new(),
+				segments: BTreeSet::new(),
 				index: cache.new_entry(),
 			},
 		}
 	}
 
-	fn insert_position(&mut self, point: Point, cache: &mut Cache) {
+	fn insert_position(&mut self, point: Point, cache: &mut Cache) -> Result<(), Error> {
 		fn insert_into_children(
 			children: &mut [Option<Node>; 8],
 			point: Point,
 			corner: Vector<3, f32>,
 			size: f32,
 			cache: &mut Cache,
-		) {
+		) -> Result<(), Error> {
 			let mut index = 0;
 			for dim in X.to(Z) {
 				if point.render.position[dim] >= corner[dim] + size / 2.0 {
@@ -68,7 +66,7 @@ impl Node {
 				}
 			}
 			match &mut children[index] {
-				Some(v) => v.insert_position(point, cache),
+				Some(v) => v.insert_position(point, cache)?,
 				None => {
 					let mut corner = corner;
 					for dim in X.to(Z) {
@@ -77,10 +75,11 @@ impl Node {
 						}
 					}
 					let mut node = Node::new_leaf(corner, size / 2.0, cache);
-					node.insert_position(point, cache);
+					node.insert_position(point, cache)?;
 					children[index] = Some(node);
 				},
 			}
+			Ok(())
 		}
 
 		fn update_value<T>(value: &mut T, update: impl FnOnce(T) -> T) {
@@ -91,27 +90,31 @@ impl Node {
 			}
 		}
 
+		// the data is always replaced, errors are returned afterwards
+		let mut result = Ok(());
 		update_value(&mut self.data, |data| match data {
 			Data::Branch { mut children, .. } => {
-				insert_into_children(&mut children, point, self.corner, self.size, cache);
+				result = insert_into_children(&mut children, point, self.corner, self.size, cache);
 				Data::Branch { children }
 			},
 			Data::Leaf { mut size, mut segments, index } if size < MAX_LEAF_SIZE => {
 				segments.insert(point.segment);
-				cache.add_value(&index, point);
+				result = cache.add_value(&index, point);
 				size += 1;
 				Data::Leaf { size, segments, index }
 			},
 			Data::Leaf { size: _, segments: _, index } => {
 				let mut children: [Option<Self>; 8] = Default::default();
-				let points = cache.read(index).read();
-				for point in points {
-					insert_into_children(&mut children, point, self.corner, self.size, cache)
-				}
-				insert_into_children(&mut children, point, self.corner, self.size, cache);
+				result = cache.read(index).read().and_then(|points| {
+					points
+						.into_iter()
+						.chain(std::iter::once(point))
+						.try_for_each(|point| insert_into_children(&mut children, point, self.corner, self.size, cache))
+				});
 				Data::Branch { children: Box::new(children) }
 			},
 		});
+		result
 	}
 
 	fn flatten(self, nodes: &mut Vec<FLatNode>, cache: &mut Cache) -> (IndexNode, u32) {
@@ -134,8 +137,8 @@ impl Node {
 					max_level + 1,
 				)
 			},
-			Data::Leaf { size, segments, index } => (
-				FlatData::Leaf { size, data: cache.read(index) },
+			Data::Leaf { segments, index, .. } => (
+				FlatData::Leaf { data: cache.read(index) },
 				IndexData::Leaf { segments },
 				1,
 			),
@@ -169,8 +172,8 @@ impl Tree {
 		Self { root: Node::new_branch(corner, size) }
 	}
 
-	pub fn insert(&mut self, point: Point, cache: &mut Cache) {
-		self.root.insert_position(point, cache);
+	pub fn insert(&mut self, point: Point, cache: &mut Cache) -> Result<(), Error> {
+		self.root.insert_position(point, cache)
 	}
 
 	pub fn flatten(
@@ -180,6 +183,7 @@ impl Tree {
 		mut cache: Cache,
 		segment_information: Vec<String>,
 		segment_values: Vec<project::Value>,
+		georeference: project::Georeference,
 	) -> (FlatTree, Project) {
 		let mut nodes = Vec::new();
 		let (tree, depth) = self.root.flatten(&mut nodes, &mut cache);
@@ -194,6 +198,7 @@ impl Tree {
 				.collect(),
 			segment_information,
 			segment_values,
+			georeference,
 		};
 
 		(flat, project)
@@ -202,13 +207,8 @@ impl Tree {
 
 #[derive(Debug)]
 pub enum FlatData {
-	Leaf {
-		size: usize,
-		data: CacheEntry<Point>,
-	},
-	Branch {
-		children: [Option<u32>; 8],
-	},
+	Leaf { data: CacheEntry<Point> },
+	Branch { children: [Option<u32>; 8] },
 }
 
 #[derive(Debug)]
@@ -225,62 +225,92 @@ pub struct FlatTree {
 }
 
 impl FlatTree {
-	pub fn save(self, writer: Writer, settings: &Settings, statistics: Statistics) {
-		let progress = Progress::new("Save Data", self.nodes.len());
+	pub fn save(
+		self,
+		writer: Writer,
+		settings: &Settings,
+		statistics: Statistics,
+		observer: &dyn Observer,
+	) -> Result<Statistics, Error> {
+		let mut progress = Progress::new("Save Data", self.nodes.len(), observer);
+		let (mut writer, mut statistics) = (writer, statistics);
 
-		let mut data = Vec::with_capacity(self.nodes.len());
-		for _ in 0..self.nodes.len() {
-			data.push(AtomicCell::new(None));
-		}
+		// children are always before their parent
+		let mut nodes = self.nodes.into_iter().map(Some).collect::<Vec<_>>();
+		let mut data = nodes.iter().map(|_| None).collect::<Vec<_>>();
+		let mut saved = vec![false; nodes.len()];
+		let mut first = 0;
+		while first < nodes.len() {
+			// the first nodes with saved children, so the files are the same for every run
+			let mut window = Vec::with_capacity(ordered::WINDOW);
+			for (i, slot) in nodes.iter_mut().enumerate().skip(first) {
+				if window.len() >= ordered::WINDOW {
+					break;
+				}
+				let Some(node) = slot else {
+					continue;
+				};
+				let children = node.children();
+				if children.iter().any(|&child| !saved[child]) {
+					continue;
+				}
+				let children = children
+					.into_iter()
+					.map(|child| data[child].take().unwrap())
+					.collect::<Vec<_>>();
+				window.push((i, slot.take().unwrap(), children));
+			}
 
-		let state = Mutex::new((progress, writer, statistics));
+			let results = window
+				.into_par_iter()
+				.map(|(i, node, children)| {
+					let is_leaf = matches!(&node.data, FlatData::Leaf { .. });
+					let index = node.index as usize;
+					Ok((i, index, is_leaf, node.save(children, settings)?))
+				})
+				.collect::<Result<Vec<_>, Error>>()?;
 
-		self.nodes
-			.into_par_iter()
-			.enumerate()
-			.for_each(|(i, node)| {
-				let is_leaf = matches!(&node.data, FlatData::Leaf { .. });
-				let index = node.index as usize;
-				let points = node.save(&data, settings);
-				let mut state = state.lock().unwrap();
-				state.0.step();
-				{
-					let writer = &mut state.1;
-					writer.save(index, &points);
-				}
+			for (i, index, is_leaf, points) in results {
+				progress.step();
+				writer.save(index, &points)?;
 				if is_leaf {
-					state.2.leaf_points += points.render.len();
+					statistics.leaf_points += points.render.len();
 				} else {
-					state.2.branch_points += points.render.len();
+					statistics.branch_points += points.render.len();
 				}
-				drop(state);
-				data[i].store(Some(points));
-			});
-		let (progress, mut writer, mut statistics) = state.into_inner().unwrap();
+				data[i] = Some(points);
+				saved[i] = true;
+			}
+			while first < nodes.len() && saved[first] {
+				first += 1;
+			}
+		}
+
 		statistics.times.lods = progress.finish();
-		writer.save_statistics(statistics);
+		observer.summary(&statistics);
+		writer.save_statistics(&statistics)?;
+		Ok(statistics)
 	}
 }
 
 impl FLatNode {
-	fn save(self, data: &[AtomicCell<Option<PointsCollection>>], settings: &Settings) -> PointsCollection {
-		match self.data {
-			FlatData::Branch { mut children } => {
-				let mut points = Vec::with_capacity(8);
-				for child in children.iter_mut().filter_map(|child| *child) {
-					let lod = loop {
-						if let Some(v) = data[child as usize].take() {
-							break v;
-						}
-						std::thread::yield_now();
-					};
-					points.push(lod);
-				}
-				level_of_detail::grid(points, self.corner, self.size, settings)
-			},
+	fn children(&self) -> Vec<usize> {
+		match &self.data {
+			FlatData::Branch { children } => children
+				.iter()
+				.flatten()
+				.map(|&child| child as usize)
+				.collect(),
+			FlatData::Leaf { .. } => Vec::new(),
+		}
+	}
+
+	fn save(self, children: Vec<PointsCollection>, settings: &Settings) -> Result<PointsCollection, Error> {
+		Ok(match self.data {
+			FlatData::Branch { .. } => level_of_detail::grid(children, self.corner, self.size, settings),
 
 			FlatData::Leaf { data, .. } => {
-				let data = data.read();
+				let data = data.read()?;
 				let points = unsafe {
 					let mut points = Vec::<project::Point>::new();
 					points.reserve_exact(data.len());
@@ -297,8 +327,11 @@ impl FLatNode {
 					height: data.iter().map(|p| p.height).collect::<Vec<_>>(),
 					curve: data.iter().map(|p| p.curve).collect::<Vec<_>>(),
 					segment: data.iter().map(|p| p.segment.get()).collect::<Vec<_>>(),
+					attributes: std::array::from_fn(|index| {
+						data.iter().map(|p| p.attributes[index]).collect::<Vec<_>>()
+					}),
 				}
 			},
-		}
+		})
 	}
 }
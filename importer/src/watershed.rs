@@ -0,0 +1,169 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use math::{Vector, X, Z};
+
+use crate::{
+	cache::{Cache, CacheIndex},
+	ground,
+	progress::{Observer, Progress},
+	segment::{remove_small, Segment, Segmenter, Tiles},
+	source::{SourcePoint, CHUNK_SIZE},
+	Error, Settings, Statistics,
+};
+
+/// Segments from the canopy height model, for dense canopies where the stems are barely visible.
+///
+/// The highest point per cell is the canopy height model. Cells with the highest value in
+/// the window of the treetop distance are treetops and the crowns grow from the treetops by
+/// a watershed on the inverted heights. The points are assigned to the crown of their cell.
+///
+/// The points are kept in chunks, so they are assigned one chunk at a time.
+pub struct ChmWatershed {
+	corner: Vector<3, f32>,
+	cell_size: f32,
+	width: usize,
+	depth: usize,
+	heights: Vec<f32>,
+	chunks: Vec<CacheIndex<SourcePoint>>,
+	length: usize,
+	treetop_distance: f32,
+	min_height: f32,
+	min_segment_size: usize,
+}
+
+impl ChmWatershed {
+	pub fn new(min: Vector<3, f32>, max: Vector<3, f32>, settings: &Settings) -> Self {
+		let width = ((max[X] - min[X]) / settings.chm_cell_size) as usize + 1;
+		let depth = ((max[Z] - min[Z]) / settings.chm_cell_size) as usize + 1;
+		Self {
+			corner: min,
+			cell_size: settings.chm_cell_size,
+			width,
+			depth,
+			heights: vec![0.0; width * depth],
+			chunks: Vec::new(),
+			length: 0,
+			treetop_distance: settings.chm_treetop_distance,
+			min_height: settings.chm_min_height,
+			min_segment_size: settings.min_segment_size,
+		}
+	}
+
+	fn cell(&self, position: Vector<3, f32>) -> usize {
+		let x = (((position[X] - self.corner[X]) / self.cell_size).max(0.0) as usize).min(self.width - 1);
+		let z = (((position[Z] - self.corner[Z]) / self.cell_size).max(0.0) as usize).min(self.depth - 1);
+		x + z * self.width
+	}
+
+	/// Local maxima above the minimum height, the highest treetop wins inside the distance.
+	fn treetops(&self) -> Vec<usize> {
+		let radius = (self.treetop_distance / self.cell_size).max(1.0) as usize;
+		let maxima = ground::dilate(&self.heights, self.width, self.depth, radius);
+		let mut candidates = (0..self.heights.len())
+			.filter(|&index| self.heights[index] >= self.min_height && self.heights[index] >= maxima[index])
+			.collect::<Vec<_>>();
+		// plateaus have multiple maxima, only the first is kept
+		candidates.sort_by(|&a, &b| self.heights[b].total_cmp(&self.heights[a]).then(a.cmp(&b)));
+
+		let mut taken = vec![false; self.heights.len()];
+		let mut treetops = Vec::new();
+		for index in candidates {
+			if taken[index] {
+				continue;
+			}
+			let (x, z) = (index % self.width, index / self.width);
+			for n_z in z.saturating_sub(radius)..(z + radius + 1).min(self.depth) {
+				for n_x in x.saturating_sub(radius)..(x + radius + 1).min(self.width) {
+					taken[n_x + n_z * self.width] = true;
+				}
+			}
+			treetops.push(index);
+		}
+		treetops
+	}
+
+	/// Grow the crowns from the treetops, the highest unlabeled neighbor is added first.
+	///
+	/// Returns the crown for every cell, `0` for cells without a crown.
+	fn watershed(&self, treetops: &[usize]) -> Vec<u32> {
+		let mut labels = vec![0; self.heights.len()];
+		let mut queue = BinaryHeap::new();
+		let mut order = 0u64;
+		for (crown, &index) in treetops.iter().enumerate() {
+			labels[index] = crown as u32 + 1;
+			queue.push((self.heights[index].to_bits(), Reverse(order), index));
+			order += 1;
+		}
+
+		while let Some((_, _, index)) = queue.pop() {
+			let (x, z) = (index % self.width, index / self.width);
+			for n_z in z.saturating_sub(1)..(z + 2).min(self.depth) {
+				for n_x in x.saturating_sub(1)..(x + 2).min(self.width) {
+					let neighbor = n_x + n_z * self.width;
+					if labels[neighbor] != 0 || self.heights[neighbor] < self.min_height {
+						continue;
+					}
+					labels[neighbor] = labels[index];
+					// heights are positive, so the bits have the same order
+					queue.push((self.heights[neighbor].to_bits(), Reverse(order), neighbor));
+					order += 1;
+				}
+			}
+		}
+		labels
+	}
+}
+
+impl Segmenter for ChmWatershed {
+	fn add_point(&mut self, point: SourcePoint, height: f32, cache: &mut Cache) -> Result<(), Error> {
+		let cell = self.cell(point.position);
+		self.heights[cell] = self.heights[cell].max(height);
+		if self.length % CHUNK_SIZE == 0 {
+			self.chunks.push(cache.new_entry());
+		}
+		self.length += 1;
+		cache.add_value(self.chunks.last().unwrap(), point)
+	}
+
+	fn segments(
+		self: Box<Self>,
+		unassigned: &mut Tiles,
+		statistics: &mut Statistics,
+		cache: &mut Cache,
+		observer: &dyn Observer,
+	) -> Result<Vec<Segment>, Error> {
+		let mut progress = Progress::new("Segmenting", self.length, observer);
+
+		let treetops = self.treetops();
+		let labels = self.watershed(&treetops);
+
+		let crowns = treetops
+			.iter()
+			.map(|_| cache.new_entry())
+			.collect::<Vec<_>>();
+		for &chunk in &self.chunks {
+			for point in cache.read(chunk).read()? {
+				match labels[self.cell(point.position)] {
+					0 => unassigned.add_point(point, cache)?,
+					crown => cache.add_value(&crowns[crown as usize - 1], point)?,
+				}
+				progress.step();
+			}
+		}
+
+		statistics.times.segment = progress.finish();
+
+		let segments = crowns
+			.into_iter()
+			.zip(treetops)
+			.map(|(crown, index)| {
+				let treetop = Vector::new([
+					self.corner[X] + ((index % self.width) as f32 + 0.5) * self.cell_size,
+					self.corner[Z] + ((index / self.width) as f32 + 0.5) * self.cell_size,
+				]);
+				Segment::new(cache.read(crown), treetop)
+			})
+			.collect();
+		remove_small(segments, self.min_segment_size, unassigned, cache)
+	}
+}
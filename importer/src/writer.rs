@@ -1,7 +1,10 @@
 use project::Project;
 use std::path::{Path, PathBuf};
 
-use crate::{point::PointsCollection, Error, Statistics};
+use crate::{attributes::Attribute, point::PointsCollection, Error, Statistics};
+
+/// File in every output folder created by the importer, so only those are removed again.
+pub const MARKER: &str = ".treee-import";
 
 pub struct Writer {
 	path: PathBuf,
@@ -10,6 +13,7 @@ pub struct Writer {
 	pub curve: project::DataFile<u32>,
 	pub height: project::DataFile<u32>,
 	pub segment: project::DataFile<u32>,
+	pub attributes: Vec<(Attribute, project::DataFile<u32>)>,
 }
 
 impl Writer {
@@ -23,56 +27,72 @@ impl Writer {
 			if path.read_dir().into_iter().flatten().next().is_some() && !project_path.exists() {
 				return Err(Error::OutputFolderIsNotEmpty);
 			}
-			std::fs::remove_dir_all(path).unwrap();
+			std::fs::remove_dir_all(path)?;
 		}
-		std::fs::create_dir_all(path).unwrap();
+		std::fs::create_dir_all(path)?;
+		std::fs::File::create(path.join(MARKER))?;
 		Ok(())
 	}
 
-	pub fn new(mut path: PathBuf, size: usize) -> Self {
+	pub fn new(mut path: PathBuf, size: usize, attributes: &[Attribute]) -> Result<Self, Error> {
 		path.push("temp.txt");
 
 		path.set_file_name("points.data");
-		let points = project::DataFile::new(size, &path);
+		let points = project::DataFile::new(size, &path)?;
 
 		path.set_file_name("slice.data");
-		let slice = project::DataFile::new(size, &path);
+		let slice = project::DataFile::new(size, &path)?;
 
 		path.set_file_name("curve.data");
-		let curve = project::DataFile::new(size, &path);
+		let curve = project::DataFile::new(size, &path)?;
 
 		path.set_file_name("height.data");
-		let height = project::DataFile::new(size, &path);
+		let height = project::DataFile::new(size, &path)?;
 
 		path.set_file_name("segment.data");
-		let segment = project::DataFile::new(size, &path);
+		let segment = project::DataFile::new(size, &path)?;
+
+		let attributes = attributes
+			.iter()
+			.map(|&attribute| {
+				path.set_file_name(format!("{}.data", attribute.property().0));
+				Ok((attribute, project::DataFile::new(size, &path)?))
+			})
+			.collect::<Result<_, Error>>()?;
 
-		Self {
+		Ok(Self {
 			points,
 			slice,
 			curve,
 			height,
 			segment,
+			attributes,
 			path,
-		}
+		})
 	}
 
-	pub fn save(&mut self, index: usize, points: &PointsCollection) {
-		self.points.save(index, &points.render);
-		self.slice.save(index, &points.slice);
-		self.height.save(index, &points.height);
-		self.curve.save(index, &points.curve);
-		self.segment.save(index, &points.segment);
+	pub fn save(&mut self, index: usize, points: &PointsCollection) -> Result<(), Error> {
+		self.points.save(index, &points.render)?;
+		self.slice.save(index, &points.slice)?;
+		self.height.save(index, &points.height)?;
+		self.curve.save(index, &points.curve)?;
+		self.segment.save(index, &points.segment)?;
+		for (attribute, file) in &mut self.attributes {
+			file.save(index, &points.attributes[attribute.index()])?;
+		}
+		Ok(())
 	}
 
-	pub fn save_project(&mut self, project: &Project) {
+	pub fn save_project(&mut self, project: &Project) -> Result<(), Error> {
 		self.path.set_file_name("project.epc");
-		project.save(&self.path);
+		project.save(&self.path)?;
+		Ok(())
 	}
 
-	pub fn save_statistics(&mut self, statistics: Statistics) {
+	pub fn save_statistics(&mut self, statistics: &Statistics) -> Result<(), Error> {
 		self.path.set_file_name("statistics.json");
-		let file = std::fs::File::create(&self.path).unwrap();
-		serde_json::to_writer_pretty(file, &statistics).unwrap();
+		let file = std::fs::File::create(&self.path)?;
+		serde_json::to_writer_pretty(file, statistics).map_err(std::io::Error::from)?;
+		Ok(())
 	}
 }
@@ -0,0 +1,244 @@
+use math::Vector;
+use std::{
+	io::{BufRead, BufReader, Seek, SeekFrom},
+	path::{Path, PathBuf},
+};
+
+use crate::{
+	attributes::{Attribute, Attributes},
+	ordered,
+	source::{to_importer, Bounds, Chunk, Source, SourcePoint, CHUNK_SIZE},
+	Error, TextFormat,
+};
+
+/// Columns for the position and the optional attributes in a line of delimited text.
+#[derive(Clone, Copy, Debug)]
+pub struct Columns {
+	pub indices: [usize; 3],
+	/// Split at whitespace if not specified.
+	pub delimiter: Option<char>,
+	/// Red, green and blue columns with the factor to 16 bit values.
+	pub color: Option<([usize; 3], f64)>,
+	/// Intensity column with the factor to 16 bit values.
+	pub intensity: Option<(usize, f64)>,
+}
+
+impl Columns {
+	/// Columns without attributes.
+	pub fn new(indices: [usize; 3], delimiter: Option<char>) -> Self {
+		Self {
+			indices,
+			delimiter,
+			color: None,
+			intensity: None,
+		}
+	}
+
+	fn attributes(&self) -> Vec<Attribute> {
+		let mut attributes = Vec::new();
+		if self.intensity.is_some() {
+			attributes.push(Attribute::Intensity);
+		}
+		if self.color.is_some() {
+			attributes.push(Attribute::Color);
+		}
+		attributes
+	}
+
+	fn parse(&self, line: &str) -> Result<Option<(Vector<3, f64>, Attributes)>, Error> {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+			return Ok(None);
+		}
+		// x, y, z, red, green, blue and intensity
+		let [x, y, z] = self.indices.map(Some);
+		let [red, green, blue] = match self.color {
+			Some((indices, _)) => indices.map(Some),
+			None => [None; 3],
+		};
+		let columns = [
+			x,
+			y,
+			z,
+			red,
+			green,
+			blue,
+			self.intensity.map(|(index, _)| index),
+		];
+		let mut values = [None; 7];
+		let mut parse = |index: usize, value: &str| -> Result<(), Error> {
+			for (field, &column) in columns.iter().enumerate() {
+				if column == Some(index) {
+					values[field] = Some(
+						value
+							.trim()
+							.parse::<f64>()
+							.map_err(|_| Error::CorruptFile)?,
+					);
+				}
+			}
+			Ok(())
+		};
+		match self.delimiter {
+			Some(delimiter) => line
+				.split(delimiter)
+				.enumerate()
+				.try_for_each(|(index, value)| parse(index, value))?,
+			None => line
+				.split_whitespace()
+				.enumerate()
+				.try_for_each(|(index, value)| parse(index, value))?,
+		}
+		let value = |field: usize, scale: f64| {
+			values[field]
+				.map(|value| (value * scale).clamp(0.0, u16::MAX as f64) as u16)
+				.ok_or(Error::CorruptFile)
+		};
+		let mut attributes = Attributes::default();
+		if let Some((_, scale)) = self.color {
+			attributes.color = [value(3, scale)?, value(4, scale)?, value(5, scale)?];
+		}
+		if let Some((_, scale)) = self.intensity {
+			attributes.intensity = value(6, scale)?;
+		}
+		match values {
+			[Some(x), Some(y), Some(z), ..] => Ok(Some((Vector::new([x, y, z]), attributes))),
+			_ => Err(Error::CorruptFile),
+		}
+	}
+}
+
+/// Points stored as lines of delimited text.
+pub struct Xyz {
+	total: usize,
+	chunks: Vec<(u64, usize)>,
+	columns: Columns,
+	bounds: Bounds,
+
+	path: PathBuf,
+}
+
+impl Xyz {
+	pub fn new(path: &Path, text: &TextFormat, delimiter: Option<char>) -> Result<Self, Error> {
+		let columns = Columns {
+			color: text
+				.text_color_columns
+				.map(|color| (color, text.text_color_scale)),
+			intensity: text.text_intensity_column.map(|intensity| (intensity, 1.0)),
+			..Columns::new(text.text_columns, text.text_delimiter.or(delimiter))
+		};
+		let skip = text.text_skip_lines + header_lines(path, text.text_skip_lines, &columns)?;
+		Self::scan(path, 0, skip, None, columns)
+	}
+
+	/// Scan the lines after `start` to calculate the bounds and chunks.
+	///
+	/// Skip the first `skip` lines and stop after `limit` points.
+	pub fn scan(path: &Path, start: u64, skip: usize, limit: Option<usize>, columns: Columns) -> Result<Self, Error> {
+		let mut file = BufReader::new(std::fs::File::open(path)?);
+		file.seek(SeekFrom::Start(start))?;
+
+		let mut position = start;
+		let mut line = String::new();
+		for _ in 0..skip {
+			line.clear();
+			position += file.read_line(&mut line)? as u64;
+		}
+
+		let limit = limit.unwrap_or(usize::MAX);
+		let mut bounds = Bounds::new();
+		let mut chunks = Vec::<(u64, usize)>::new();
+		let mut total = 0;
+		while total < limit {
+			line.clear();
+			let length = file.read_line(&mut line)?;
+			if length == 0 {
+				break;
+			}
+			if let Some((point, _)) = columns.parse(&line)? {
+				if total % CHUNK_SIZE == 0 {
+					chunks.push((position, 0));
+				}
+				chunks.last_mut().unwrap().1 += 1;
+				bounds.insert(to_importer(point));
+				total += 1;
+			}
+			position += length as u64;
+		}
+		if limit != usize::MAX && total < limit {
+			return Err(Error::CorruptFile);
+		}
+		if bounds.is_empty() {
+			bounds.insert(Vector::default());
+		}
+
+		Ok(Self {
+			total,
+			chunks,
+			columns,
+			bounds,
+			path: path.to_owned(),
+		})
+	}
+}
+
+/// Lines of a header after the first `skip` lines.
+///
+/// The first line with content is a header if it contains letters and can not be parsed.
+fn header_lines(path: &Path, skip: usize, columns: &Columns) -> Result<usize, Error> {
+	let file = BufReader::new(std::fs::File::open(path)?);
+	for (index, line) in file.lines().skip(skip).enumerate() {
+		let line = line?;
+		match columns.parse(&line) {
+			Ok(None) => continue,
+			Err(_) if line.contains(|c: char| c.is_alphabetic()) => return Ok(index + 1),
+			_ => return Ok(0),
+		}
+	}
+	Ok(0)
+}
+
+impl Source for Xyz {
+	fn bounds(&self) -> Bounds {
+		self.bounds
+	}
+
+	fn total(&self) -> usize {
+		self.total
+	}
+
+	fn attributes(&self) -> Vec<Attribute> {
+		self.columns.attributes()
+	}
+
+	fn read(self: Box<Self>, center: Vector<3, f64>, cb: &(dyn Fn(Chunk) + Sync)) -> Result<(), Error> {
+		ordered::try_for_each(
+			&self.chunks,
+			|&(s, l)| {
+				let mut file = BufReader::new(std::fs::File::open(&self.path)?);
+				file.seek(SeekFrom::Start(s))?;
+
+				let mut points = Vec::with_capacity(l);
+				let mut line = String::new();
+				while points.len() < l {
+					line.clear();
+					if file.read_line(&mut line)? == 0 {
+						return Err(Error::CorruptFile);
+					}
+					if let Some((position, attributes)) = self.columns.parse(&line)? {
+						points.push(SourcePoint {
+							position: (to_importer(position) - center).map(|x| x as f32),
+							attributes,
+						});
+					}
+				}
+
+				Ok(Chunk::from_points(points))
+			},
+			|chunk| {
+				cb(chunk);
+				Ok(())
+			},
+		)
+	}
+}
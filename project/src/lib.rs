@@ -1,23 +1,63 @@
 use std::{
-	collections::HashSet,
+	collections::BTreeSet,
 	fs::File,
 	io::{Read, Seek, Write},
 	num::NonZeroU32,
 	path::Path,
 };
 
-use math::Vector;
+mod migration;
+
+use math::{Vector, X, Y, Z};
 use serde::{Deserialize, Serialize};
 
 pub const MAX_LEAF_SIZE: usize = 1 << 15;
 
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+	#[error(transparent)]
+	Io(#[from] std::io::Error),
+
+	#[error("Corrupt or foreign data: {0}")]
+	Corrupt(String),
+
+	#[error("Index {index} is out of range for {size} entries")]
+	OutOfRange { index: usize, size: usize },
+
+	#[error("File version {version} is newer than the supported version {supported}")]
+	UnsupportedVersion { version: u32, supported: u32 },
+}
+
+impl From<bincode::Error> for Error {
+	fn from(err: bincode::Error) -> Self {
+		match *err {
+			bincode::ErrorKind::Io(err) => Self::Io(err),
+			err => Self::Corrupt(err.to_string()),
+		}
+	}
+}
+
+/// Property with colors packed as `0x00RRGGBB` instead of values for a lookup.
+pub const COLOR_PROPERTY: &str = "color";
+
+/// Segment for points, which are not part of any tree.
+pub const UNASSIGNED_SEGMENT: u32 = u32::MAX;
+
+/// Segment for points classified as ground.
+pub const GROUND_SEGMENT: u32 = u32::MAX - 1;
+
+/// Reserved segments have no segment file and no segment information.
+pub fn is_reserved_segment(segment: u32) -> bool {
+	segment >= GROUND_SEGMENT
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub enum IndexData {
 	Branch {
 		children: Box<[Option<IndexNode>; 8]>,
 	},
 	Leaf {
-		segments: HashSet<NonZeroU32>,
+		segments: BTreeSet<NonZeroU32>,
 	},
 }
 
@@ -38,12 +78,43 @@ pub struct Project {
 
 	pub segment_information: Vec<String>,
 	pub segment_values: Vec<Value>,
+
+	pub georeference: Georeference,
 }
 
 impl Project {
-	pub fn from_file(path: impl AsRef<Path>) -> Self {
-		let file = std::fs::OpenOptions::new().read(true).open(path).unwrap();
-		bincode::deserialize_from(file).unwrap()
+	/// Start of every project file, followed by the version.
+	pub const MAGIC: [u8; 8] = *b"TREEEPC\0";
+	/// Version of the saved project, older versions are migrated when loading.
+	pub const VERSION: u32 = 1;
+
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+		let file = std::fs::OpenOptions::new().read(true).open(path)?;
+		let length = file.metadata()?.len();
+		let mut reader = std::io::BufReader::new(file);
+
+		let mut header = [0u8; 12];
+		let version = if length >= header.len() as u64 {
+			reader.read_exact(&mut header)?;
+			if header[..8] == Self::MAGIC {
+				Some(u32::from_le_bytes([
+					header[8], header[9], header[10], header[11],
+				]))
+			} else {
+				None
+			}
+		} else {
+			None
+		};
+		// files without the header are from the first version
+		let version = match version {
+			Some(version) => version,
+			None => {
+				reader.rewind()?;
+				0
+			},
+		};
+		migration::load(version, reader, length)
 	}
 
 	pub fn empty() -> Self {
@@ -51,7 +122,7 @@ impl Project {
 			name: "No Project loaded".into(),
 			depth: 0,
 			root: IndexNode {
-				data: IndexData::Leaf { segments: HashSet::new() },
+				data: IndexData::Leaf { segments: BTreeSet::new() },
 				position: Vector::default(),
 				size: 0.0,
 				index: 0,
@@ -59,21 +130,75 @@ impl Project {
 			properties: vec![(String::from("None"), String::from("None"), 1)],
 			segment_information: Vec::new(),
 			segment_values: Vec::new(),
+			georeference: Georeference::default(),
 		}
 	}
 
-	pub fn save(&self, path: impl AsRef<Path>) {
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
 		let file = std::fs::OpenOptions::new()
 			.write(true)
 			.create(true)
-			.open(path)
-			.unwrap();
-		bincode::serialize_into(file, self).unwrap();
+			.truncate(true)
+			.open(path)?;
+		let mut writer = std::io::BufWriter::new(file);
+		writer.write_all(&Self::MAGIC)?;
+		writer.write_all(&Self::VERSION.to_le_bytes())?;
+		bincode::serialize_into(&mut writer, self)?;
+		writer.flush()?;
+		Ok(())
+	}
+
+	/// Values of the segment, `None` for reserved segments and segments outside the project.
+	pub fn segment(&self, index: NonZeroU32) -> Option<&[Value]> {
+		let offset = (index.get() as usize - 1).checked_mul(self.segment_information.len())?;
+		self.segment_values
+			.get(offset..(offset + self.segment_information.len()))
+	}
+}
+
+/// Conversion between the source coordinates and the project coordinates.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Axes {
+	/// Source is z-up, project position is (x, z, -y).
+	#[default]
+	ZUp,
+}
+
+impl Axes {
+	pub fn to_project(self, position: Vector<3, f64>) -> Vector<3, f64> {
+		match self {
+			Self::ZUp => Vector::new([position[X], position[Z], -position[Y]]),
+		}
 	}
 
-	pub fn segment(&self, index: NonZeroU32) -> &[Value] {
-		let offset = (index.get() as usize - 1) * self.segment_information.len();
-		&self.segment_values[offset..(offset + self.segment_information.len())]
+	pub fn to_source(self, position: Vector<3, f64>) -> Vector<3, f64> {
+		match self {
+			Self::ZUp => Vector::new([position[X], -position[Z], position[Y]]),
+		}
+	}
+}
+
+/// Location of the project in the source coordinate system.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Georeference {
+	/// Source position of the project origin.
+	pub origin: Vector<3, f64>,
+	pub axes: Axes,
+	/// Coordinate reference system as WKT or `EPSG:<code>`, if known.
+	pub crs: Option<String>,
+}
+
+impl Georeference {
+	/// Convert a project position back to source coordinates.
+	pub fn to_source(&self, position: Vector<3, f32>) -> Vector<3, f64> {
+		self.axes.to_source(position.map(|x| x as f64)) + self.origin
+	}
+
+	/// Convert source coordinates into a project position.
+	pub fn to_project(&self, position: Vector<3, f64>) -> Vector<3, f32> {
+		self.axes
+			.to_project(position - self.origin)
+			.map(|x| x as f32)
 	}
 }
 
@@ -102,81 +227,347 @@ impl std::fmt::Display for Value {
 	}
 }
 
+/// Values stored in a [`DataFile`].
+pub trait Element: Copy + bytemuck::Pod {
+	/// Identifies the type in the file header.
+	const TYPE: [u8; 4];
+}
+
+impl Element for Point {
+	const TYPE: [u8; 4] = *b"PNT\0";
+}
+
+impl Element for u32 {
+	const TYPE: [u8; 4] = *b"U32\0";
+}
+
+/// Start of every data file.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DataHeader {
+	magic: [u8; 8],
+	version: u32,
+	element_type: [u8; 4],
+	element_size: u32,
+	reserved: u32,
+	entries: u64,
+}
+
+impl DataHeader {
+	const MAGIC: [u8; 8] = *b"TREEDAT\0";
+	const VERSION: u32 = 1;
+	const SIZE: u64 = std::mem::size_of::<Self>() as u64;
+}
+
+/// File with a list of values for every entry.
+///
+/// The header is followed by the position and length of every entry and the values.
+/// Files from before the header are read with the table at the start.
 pub struct DataFile<T>
 where
-	T: Copy + bytemuck::Pod,
+	T: Element,
 {
 	file: File,
+	/// Start of the entry table.
+	offset: u64,
+	entries: usize,
 	phantom: std::marker::PhantomData<T>,
 }
 
 impl<T> DataFile<T>
 where
-	T: Copy + bytemuck::Pod,
+	T: Element,
 {
-	pub fn new(size: usize, path: impl AsRef<Path>) -> Self {
-		let file = std::fs::OpenOptions::new()
+	const ENTRY_SIZE: u64 = 2 * std::mem::size_of::<u64>() as u64;
+
+	fn header(entries: usize) -> DataHeader {
+		DataHeader {
+			magic: DataHeader::MAGIC,
+			version: DataHeader::VERSION,
+			element_type: T::TYPE,
+			element_size: std::mem::size_of::<T>() as u32,
+			reserved: 0,
+			entries: entries as u64,
+		}
+	}
+
+	pub fn new(size: usize, path: impl AsRef<Path>) -> Result<Self, Error> {
+		let mut file = std::fs::OpenOptions::new()
 			.write(true)
 			.create(true)
-			.open(path.as_ref())
-			.unwrap();
-		file.set_len((size * 2 * std::mem::size_of::<u64>()) as u64)
-			.unwrap();
-		Self { file, phantom: std::marker::PhantomData }
+			.truncate(true)
+			.open(path.as_ref())?;
+		file.write_all(bytemuck::bytes_of(&Self::header(size)))?;
+		file.set_len(DataHeader::SIZE + size as u64 * Self::ENTRY_SIZE)?;
+		Ok(Self {
+			file,
+			offset: DataHeader::SIZE,
+			entries: size,
+			phantom: std::marker::PhantomData,
+		})
 	}
 
-	pub fn open(path: impl AsRef<Path>) -> Self {
-		Self {
-			file: std::fs::OpenOptions::new()
-				.read(true)
-				.open(path.as_ref())
-				.unwrap(),
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+		let mut file = std::fs::OpenOptions::new().read(true).open(path.as_ref())?;
+		let length = file.metadata()?.len();
+
+		let mut header: DataHeader = bytemuck::Zeroable::zeroed();
+		if length >= DataHeader::SIZE {
+			file.read_exact(bytemuck::bytes_of_mut(&mut header))?;
+		}
+		if header.magic != DataHeader::MAGIC {
+			let entries = Self::legacy_entries(&mut file, length)?;
+			return Ok(Self {
+				file,
+				offset: 0,
+				entries,
+				phantom: std::marker::PhantomData,
+			});
+		}
+
+		if header.version > DataHeader::VERSION {
+			return Err(Error::UnsupportedVersion {
+				version: header.version,
+				supported: DataHeader::VERSION,
+			});
+		}
+		if header.element_type != T::TYPE || header.element_size as usize != std::mem::size_of::<T>() {
+			return Err(Error::Corrupt(format!(
+				"expected element type {} with {} bytes, found type {} with {} bytes",
+				String::from_utf8_lossy(&T::TYPE).trim_end_matches('\0'),
+				std::mem::size_of::<T>(),
+				String::from_utf8_lossy(&header.element_type).trim_end_matches('\0'),
+				header.element_size,
+			)));
+		}
+		let table_end = header
+			.entries
+			.checked_mul(Self::ENTRY_SIZE)
+			.and_then(|size| size.checked_add(DataHeader::SIZE));
+		if !matches!(table_end, Some(end) if end <= length) {
+			return Err(Error::Corrupt(format!(
+				"entry table with {} entries is outside of the file",
+				header.entries
+			)));
+		}
+		Ok(Self {
+			file,
+			offset: DataHeader::SIZE,
+			entries: header.entries as usize,
 			phantom: std::marker::PhantomData,
+		})
+	}
+
+	/// Entry count of a file from before the header.
+	///
+	/// The values were appended after the entry table, so the table ends at the first value.
+	fn legacy_entries(file: &mut File, length: u64) -> Result<usize, Error> {
+		file.rewind()?;
+		let mut reader = std::io::BufReader::new(&mut *file);
+		let mut end = length - length % Self::ENTRY_SIZE;
+		let mut position = 0;
+		while position < end {
+			let mut pos = [0u64, 0u64];
+			reader.read_exact(bytemuck::cast_slice_mut(&mut pos))?;
+			position += Self::ENTRY_SIZE;
+			// entries without values were never saved
+			if pos[0] != 0 {
+				end = end.min(pos[0]);
+			}
 		}
+		Ok((end / Self::ENTRY_SIZE) as usize)
 	}
 
 	pub fn fake() -> Self {
 		let mut file = tempfile::tempfile().unwrap();
+		file.write_all(bytemuck::bytes_of(&Self::header(1)))
+			.unwrap();
 		file.write_all(bytemuck::cast_slice(&[0u64, 0u64])).unwrap();
-		Self { file, phantom: std::marker::PhantomData }
+		Self {
+			file,
+			offset: DataHeader::SIZE,
+			entries: 1,
+			phantom: std::marker::PhantomData,
+		}
 	}
 
-	pub fn save(&mut self, idx: usize, data: &[T]) {
-		self.file.seek(std::io::SeekFrom::End(0)).unwrap();
-		let pos = [self.file.stream_position().unwrap(), data.len() as u64];
-		self.file.write_all(bytemuck::cast_slice(data)).unwrap();
-		self.file
-			.seek(std::io::SeekFrom::Start(
-				(idx * 2 * std::mem::size_of::<u64>()) as u64,
-			))
-			.unwrap();
-		self.file.write_all(bytemuck::cast_slice(&pos)).unwrap();
+	/// Number of entries in the file.
+	pub fn len(&self) -> usize {
+		self.entries
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries == 0
+	}
+
+	fn check_index(&self, idx: usize) -> Result<(), Error> {
+		if idx >= self.entries {
+			return Err(Error::OutOfRange { index: idx, size: self.entries });
+		}
+		Ok(())
+	}
+
+	pub fn save(&mut self, idx: usize, data: &[T]) -> Result<(), Error> {
+		self.check_index(idx)?;
+		self.file.seek(std::io::SeekFrom::End(0))?;
+		let pos = [self.file.stream_position()?, data.len() as u64];
+		self.file.write_all(bytemuck::cast_slice(data))?;
+		self.file.seek(std::io::SeekFrom::Start(
+			self.offset + idx as u64 * Self::ENTRY_SIZE,
+		))?;
+		self.file.write_all(bytemuck::cast_slice(&pos))?;
+		Ok(())
 	}
 
-	pub fn read(&mut self, idx: usize) -> Vec<T> {
+	pub fn read(&mut self, idx: usize) -> Result<Vec<T>, Error> {
+		self.check_index(idx)?;
+		let length = self.file.metadata()?.len();
 		let mut pos = [0u64, 0u64];
-		self.file
-			.seek(std::io::SeekFrom::Start(
-				(idx * 2 * std::mem::size_of::<u64>()) as u64,
-			))
-			.unwrap();
-		self.file
-			.read_exact(bytemuck::cast_slice_mut(&mut pos))
-			.unwrap();
-		self.file.seek(std::io::SeekFrom::Start(pos[0])).unwrap();
+		self.file.seek(std::io::SeekFrom::Start(
+			self.offset + idx as u64 * Self::ENTRY_SIZE,
+		))?;
+		self.file.read_exact(bytemuck::cast_slice_mut(&mut pos))?;
+		let end = pos[1]
+			.checked_mul(std::mem::size_of::<T>() as u64)
+			.and_then(|bytes| bytes.checked_add(pos[0]));
+		if !matches!(end, Some(end) if end <= length) {
+			return Err(Error::Corrupt(format!(
+				"entry {} with {} values at {} is outside of the file",
+				idx, pos[1], pos[0]
+			)));
+		}
+		self.file.seek(std::io::SeekFrom::Start(pos[0]))?;
 		let mut buffer = vec![T::zeroed(); pos[1] as usize];
 		self.file
-			.read_exact(bytemuck::cast_slice_mut(&mut buffer))
-			.unwrap();
-		buffer
+			.read_exact(bytemuck::cast_slice_mut(&mut buffer))?;
+		Ok(buffer)
 	}
 
-	pub fn sizes(&mut self, size: usize) -> Vec<[u64; 2]> {
+	pub fn sizes(&mut self, size: usize) -> Result<Vec<[u64; 2]>, Error> {
+		if size > self.entries {
+			return Err(Error::OutOfRange { index: size - 1, size: self.entries });
+		}
 		let mut buffer = vec![[0, 0]; size];
-		self.file.seek(std::io::SeekFrom::Start(0)).unwrap();
+		self.file.seek(std::io::SeekFrom::Start(self.offset))?;
 		self.file
-			.read_exact(bytemuck::cast_slice_mut(&mut buffer))
+			.read_exact(bytemuck::cast_slice_mut(&mut buffer))?;
+		Ok(buffer)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Project with a branch and two leaves, saved with the indices 0 to 2.
+	fn nodes() -> IndexNode {
+		let leaf = |index: u32| IndexNode {
+			data: IndexData::Leaf {
+				segments: BTreeSet::from([NonZeroU32::new(1).unwrap()]),
+			},
+			position: Vector::new([index as f32, 0.0, 0.0]),
+			size: 1.0,
+			index,
+		};
+		let mut children: [Option<IndexNode>; 8] = Default::default();
+		children[0] = Some(leaf(0));
+		children[1] = Some(leaf(1));
+		IndexNode {
+			data: IndexData::Branch { children: Box::new(children) },
+			position: Vector::default(),
+			size: 2.0,
+			index: 2,
+		}
+	}
+
+	/// Data file like before the header, with the entry table at the start followed by the values.
+	fn save_legacy(path: &Path, entries: &[Vec<u32>], order: &[usize]) {
+		let mut file = std::fs::OpenOptions::new()
+			.read(true)
+			.write(true)
+			.create(true)
+			.truncate(true)
+			.open(path)
+			.unwrap();
+		file.set_len((entries.len() * 2 * std::mem::size_of::<u64>()) as u64)
+			.unwrap();
+		for &index in order {
+			file.seek(std::io::SeekFrom::End(0)).unwrap();
+			let pos = [file.stream_position().unwrap(), entries[index].len() as u64];
+			file.write_all(bytemuck::cast_slice(&entries[index]))
+				.unwrap();
+			file.seek(std::io::SeekFrom::Start(
+				(index * 2 * std::mem::size_of::<u64>()) as u64,
+			))
 			.unwrap();
-		buffer
+			file.write_all(bytemuck::cast_slice(&pos)).unwrap();
+		}
+	}
+
+	#[test]
+	fn load_version_0() {
+		let folder = tempfile::tempdir().unwrap();
+
+		// the first version serialized the fields without a header and without the georeference
+		let legacy = (
+			String::from("legacy"),
+			2u32,
+			nodes(),
+			vec![(String::from("segment"), String::from("Segment"), 1u32)],
+			vec![String::from("Trunk")],
+			vec![Value::Percent(0.5)],
+		);
+		let path = folder.path().join("project.epc");
+		bincode::serialize_into(File::create(&path).unwrap(), &legacy).unwrap();
+		let project = Project::from_file(&path).unwrap();
+		assert_eq!(project.name, "legacy");
+		assert_eq!(project.depth, 2);
+		assert_eq!(project.root.index, 2);
+		assert_eq!(project.properties, legacy.3);
+		assert!(project.georeference.crs.is_none());
+
+		let entries = vec![vec![1, 2, 3], vec![4], vec![5, 6]];
+		let path = folder.path().join("segment.data");
+		save_legacy(&path, &entries, &[1, 0, 2]);
+		let mut data = DataFile::<u32>::open(&path).unwrap();
+		assert_eq!(data.len(), project.root.index as usize + 1);
+		for (index, values) in entries.iter().enumerate() {
+			assert_eq!(&data.read(index).unwrap(), values);
+		}
+		assert!(matches!(
+			data.read(entries.len()),
+			Err(Error::OutOfRange { index: 3, size: 3 })
+		));
+	}
+
+	#[test]
+	fn save_and_load() {
+		let folder = tempfile::tempdir().unwrap();
+		let mut project = Project::empty();
+		project.root = nodes();
+		project.segment_information = vec![String::from("Trunk"), String::from("Crown")];
+		project.segment_values = vec![Value::Percent(0.25), Value::Percent(0.75)];
+		project.georeference.crs = Some(String::from("EPSG:2056"));
+		let path = folder.path().join("project.epc");
+		project.save(&path).unwrap();
+
+		let loaded = Project::from_file(&path).unwrap();
+		assert_eq!(loaded.root.index, 2);
+		assert_eq!(loaded.georeference.crs.as_deref(), Some("EPSG:2056"));
+		assert!(loaded.segment(NonZeroU32::new(1).unwrap()).is_some());
+		assert!(loaded.segment(NonZeroU32::new(2).unwrap()).is_none());
+		assert!(loaded
+			.segment(NonZeroU32::new(UNASSIGNED_SEGMENT).unwrap())
+			.is_none());
+
+		let path = folder.path().join("segment.data");
+		let mut data = DataFile::<u32>::new(3, &path).unwrap();
+		data.save(2, &[7, 8]).unwrap();
+		data.save(0, &[9]).unwrap();
+		let mut data = DataFile::<u32>::open(&path).unwrap();
+		assert_eq!(data.len(), 3);
+		assert_eq!(data.read(0).unwrap(), [9]);
+		assert_eq!(data.read(1).unwrap(), Vec::<u32>::new());
+		assert_eq!(data.read(2).unwrap(), [7, 8]);
 	}
 }
@@ -0,0 +1,52 @@
+//! Older versions of the project file, which are converted into the current version.
+//!
+//! Every change to the serialized types needs a new version with a frozen copy of the
+//! old layout and a conversion into the next version.
+
+use std::io::Read;
+
+use bincode::Options;
+use serde::Deserialize;
+
+use crate::{Error, Georeference, IndexNode, Project, Value};
+
+/// First version without a header and without the georeference.
+///
+/// `IndexNode` and `Value` are unchanged since.
+#[derive(Deserialize)]
+struct V0 {
+	name: String,
+	depth: u32,
+	root: IndexNode,
+	properties: Vec<(String, String, u32)>,
+	segment_information: Vec<String>,
+	segment_values: Vec<Value>,
+}
+
+impl From<V0> for Project {
+	fn from(project: V0) -> Self {
+		Self {
+			name: project.name,
+			depth: project.depth,
+			root: project.root,
+			properties: project.properties,
+			segment_information: project.segment_information,
+			segment_values: project.segment_values,
+			georeference: Georeference::default(),
+		}
+	}
+}
+
+/// Load a project with the version from the header.
+pub(crate) fn load(version: u32, reader: impl Read, limit: u64) -> Result<Project, Error> {
+	// the limit prevents huge allocations for lengths read from foreign files
+	let options = bincode::DefaultOptions::new()
+		.with_fixint_encoding()
+		.allow_trailing_bytes()
+		.with_limit(limit);
+	match version {
+		0 => Ok(options.deserialize_from::<_, V0>(reader)?.into()),
+		Project::VERSION => Ok(options.deserialize_from(reader)?),
+		version => Err(Error::UnsupportedVersion { version, supported: Project::VERSION }),
+	}
+}
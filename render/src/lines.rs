@@ -88,7 +88,7 @@ where
 	fn render_lines(&mut self, value: &'a V, state: &'a S, context: &'a C, camera: &'a Camera3DGPU) {
 		self.set_pipeline(&state.get().pipeline);
 		self.set_bind_group(0, camera.get_bind_group(), &[]);
-		let lines_pass = unsafe { std::mem::transmute::<_, &mut LinesPass<'a>>(self) };
+		let lines_pass = unsafe { std::mem::transmute::<&mut RenderPass<'a>, &mut LinesPass<'a>>(self) };
 		value.render(context, lines_pass);
 	}
 }
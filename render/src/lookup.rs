@@ -9,6 +9,15 @@ pub struct Lookup {
 
 impl Lookup {
 	pub fn new_png(state: &impl Has<State>, data: &[u8], range: u32) -> Self {
+		Self::new(state, data, Some(range))
+	}
+
+	/// Use the values as packed `0x00RRGGBB` colors instead of the gradient.
+	pub fn new_color(state: &impl Has<State>, data: &[u8]) -> Self {
+		Self::new(state, data, None)
+	}
+
+	fn new(state: &impl Has<State>, data: &[u8], range: Option<u32>) -> Self {
 		let state = state.get();
 		let texture = Texture::new_1d(state, data);
 		assert!(texture.size[X].is_power_of_two());
@@ -18,13 +27,16 @@ impl Lookup {
 
 		let bind_group_layout = Self::get_layout(state);
 
-		let scale = range / texture.size[X] + 1;
+		let uniform = match range {
+			Some(range) => [range / texture.size[X] + 1, 0],
+			None => [1, 1],
+		};
 
 		let buffer = state
 			.device
 			.create_buffer_init(&wgpu::util::BufferInitDescriptor {
 				label: Some("Camera Buffer"),
-				contents: bytemuck::cast_slice(&[scale]),
+				contents: bytemuck::cast_slice(&uniform),
 				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
 			});
 
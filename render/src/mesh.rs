@@ -113,7 +113,7 @@ where
 		self.set_pipeline(&state.get().pipeline);
 		self.set_bind_group(0, camera.get_bind_group(), &[]);
 		self.set_bind_group(1, lookup.get_bind_group(), &[]);
-		let lines_pass = unsafe { std::mem::transmute::<_, &mut MeshPass<'a>>(self) };
+		let lines_pass = unsafe { std::mem::transmute::<&mut RenderPass<'a>, &mut MeshPass<'a>>(self) };
 		value.render(lines_pass);
 	}
 }
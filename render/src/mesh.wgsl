@@ -34,6 +34,7 @@ fn vs_main(
 
 struct LookupUniform {
     scale: u32,
+    color: u32,
 };
 
 @group(1) @binding(0)
@@ -43,6 +44,15 @@ var<uniform> lookup_uniform: LookupUniform;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if lookup_uniform.color != 0u {
+        return color(in.value);
+    }
     let idx = in.value / lookup_uniform.scale;
     return textureLoad(lookup, idx, 0);
 }
+
+// packed as 0x00RRGGBB in sRGB
+fn color(value: u32) -> vec4<f32> {
+    let srgb = unpack4x8unorm(value).zyx;
+    return vec4<f32>(pow(srgb, vec3<f32>(2.2)), 1.0);
+}
@@ -13,6 +13,7 @@ unsafe impl bytemuck::Pod for PointEdge {}
 const BASE_ATTRIBUTES: [wgpu::VertexAttribute; 1] = vertex_attr_array![0 => Float32x2];
 const ATTRIBUTES: [wgpu::VertexAttribute; 3] = vertex_attr_array![1 => Float32x3, 2 => Float32x3, 3 => Float32];
 const PROPERTY_ATTRIBUTES: [wgpu::VertexAttribute; 1] = vertex_attr_array![4 => Uint32];
+const SEGMENT_ATTRIBUTES: [wgpu::VertexAttribute; 1] = vertex_attr_array![5 => Uint32];
 
 pub fn point_base_description<'a>() -> wgpu::VertexBufferLayout<'a> {
 	wgpu::VertexBufferLayout {
@@ -37,3 +38,11 @@ pub fn point_property_description<'a>(step_mode: wgpu::VertexStepMode) -> wgpu::
 		attributes: &PROPERTY_ATTRIBUTES,
 	}
 }
+
+pub fn point_segment_description<'a>(step_mode: wgpu::VertexStepMode) -> wgpu::VertexBufferLayout<'a> {
+	wgpu::VertexBufferLayout {
+		array_stride: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+		step_mode,
+		attributes: &SEGMENT_ATTRIBUTES,
+	}
+}
@@ -1,10 +1,12 @@
+#![allow(unexpected_cfgs)]
+
 use math::Vector;
 use project::{Point, MAX_LEAF_SIZE};
 use wgpu::util::DeviceExt;
 
 use crate::{
-	depth_texture::DepthTexture, point_base_description, point_description, point_property_description, Camera3DGPU,
-	Has, Lookup, PointEdge, RenderPass, State,
+	depth_texture::DepthTexture, point_base_description, point_description, point_property_description,
+	point_segment_description, Camera3DGPU, Has, Lookup, PointEdge, RenderPass, State,
 };
 
 pub struct PointCloudState {
@@ -104,6 +106,7 @@ impl PointCloudState {
 						point_base_description(),
 						point_description(wgpu::VertexStepMode::Instance),
 						point_property_description(wgpu::VertexStepMode::Instance),
+						point_segment_description(wgpu::VertexStepMode::Instance),
 					],
 				},
 				fragment: Some(wgpu::FragmentState {
@@ -190,7 +193,7 @@ where
 		self.set_bind_group(1, &environment.bind_group, &[]);
 		self.set_bind_group(2, lookup.get_bind_group(), &[]);
 		self.set_vertex_buffer(0, state.get().base.slice(..));
-		let lines_pass = unsafe { std::mem::transmute::<_, &mut PointCloudPass<'a>>(self) };
+		let lines_pass = unsafe { std::mem::transmute::<&mut RenderPass<'a>, &mut PointCloudPass<'a>>(self) };
 		value.render(context, lines_pass);
 	}
 }
@@ -198,11 +201,18 @@ where
 #[derive(Debug)]
 pub struct PointCloud {
 	pub buffer: wgpu::Buffer,
+	pub segment: wgpu::Buffer,
 	pub instances: u32,
 }
 
 impl PointCloud {
 	pub fn new(state: &impl Has<State>, vertices: &[Point]) -> Self {
+		Self::with_segments(state, vertices, &vec![0; vertices.len()])
+	}
+
+	/// Points with their segment, which allows to hide the reserved segments.
+	pub fn with_segments(state: &impl Has<State>, vertices: &[Point], segments: &[u32]) -> Self {
+		assert_eq!(vertices.len(), segments.len());
 		let buffer = state
 			.get()
 			.device
@@ -211,8 +221,20 @@ impl PointCloud {
 				contents: bytemuck::cast_slice(vertices),
 				usage: wgpu::BufferUsages::VERTEX,
 			});
+		let segment = state
+			.get()
+			.device
+			.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("point cloud segment buffer"),
+				contents: bytemuck::cast_slice(segments),
+				usage: wgpu::BufferUsages::VERTEX,
+			});
 
-		Self { buffer, instances: vertices.len() as u32 }
+		Self {
+			buffer,
+			segment,
+			instances: vertices.len() as u32,
+		}
 	}
 
 	pub fn render<'a>(&'a self, point_cloud_pass: &mut PointCloudPass<'a>, property: &'a PointCloudProperty) {
@@ -225,6 +247,9 @@ impl PointCloud {
 				.buffer
 				.slice(0..(self.instances * std::mem::size_of::<u32>() as u32) as wgpu::BufferAddress),
 		);
+		point_cloud_pass
+			.0
+			.set_vertex_buffer(3, self.segment.slice(..));
 		if property.length != 0 {
 			assert!(
 				property.length == self.instances,
@@ -275,20 +300,22 @@ pub struct PointCloudEnvironment {
 	pub min: u32,
 	pub max: u32,
 	pub scale: f32,
+	/// Bit `i` hides the points in the reserved segment `u32::MAX - i`.
+	pub hidden: u32,
 }
 
 impl PointCloudEnvironment {
-	pub fn new(state: &impl Has<State>, min: u32, max: u32, scale: f32) -> Self {
+	pub fn new(state: &impl Has<State>, min: u32, max: u32, scale: f32, hidden: u32) -> Self {
 		#[repr(C)]
 		#[derive(Debug, Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
 		struct Uniform {
 			scale: f32,
 			min: u32,
 			max: u32,
-			pad: [u32; 2],
+			hidden: u32,
 		}
 
-		let uniform = Uniform { scale, min, max, pad: [0, 0] };
+		let uniform = Uniform { scale, min, max, hidden };
 		let buffer = state
 			.get()
 			.device
@@ -309,7 +336,7 @@ impl PointCloudEnvironment {
 				}],
 				label: Some("point cloud environment bindgroup"),
 			});
-		Self { bind_group, min, max, scale }
+		Self { bind_group, min, max, scale, hidden }
 	}
 
 	pub fn get_layout(state: &impl Has<State>) -> wgpu::BindGroupLayout {
@@ -6,6 +6,7 @@ struct Environment {
     scale: f32,
     min: u32,
     max: u32,
+    hidden: u32,
 }
 
 @group(0) @binding(0)
@@ -27,6 +28,10 @@ struct PropertyInput {
     @location(4) value: u32,
 }
 
+struct SegmentInput {
+    @location(5) segment: u32,
+}
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) @interpolate(flat) value: u32,
@@ -39,9 +44,13 @@ fn vs_main(
     vertex_in: VertexInput,
     instance_in: InstanceInput,
     property_in: PropertyInput,
+    segment_in: SegmentInput,
 ) -> VertexOutput {
     var out: VertexOutput;
-    if  property_in.value < environment.min || environment.max < property_in.value {
+    // reserved segments count down from the largest value
+    let reserved = 0xFFFFFFFFu - segment_in.segment;
+    let hidden = reserved < 32u && ((environment.hidden >> reserved) & 1u) != 0u;
+    if  hidden || property_in.value < environment.min || environment.max < property_in.value {
         out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 0.0);
         out.value = 0u;
         out.pos = vec2<f32>(0.0, 0.0);
@@ -64,6 +73,7 @@ fn vs_main(
 
 struct LookupUniform {
     scale: u32,
+    color: u32,
 };
 
 @group(2) @binding(0)
@@ -77,6 +87,19 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
         discard;
     }
     // return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    if lookup_uniform.color != 0u {
+        return color(in.value);
+    }
     let idx = in.value / lookup_uniform.scale;
+    // values above the range, like the reserved segments, are gray
+    if idx >= textureDimensions(lookup) {
+        return vec4<f32>(0.5, 0.5, 0.5, 1.0);
+    }
     return textureLoad(lookup, idx, 0);
 }
+
+// packed as 0x00RRGGBB in sRGB
+fn color(value: u32) -> vec4<f32> {
+    let srgb = unpack4x8unorm(value).zyx;
+    return vec4<f32>(pow(srgb, vec3<f32>(2.2)), 1.0);
+}
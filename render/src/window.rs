@@ -76,7 +76,7 @@ impl Window {
 
 	pub fn screen_shot<S: Has<State>>(&mut self, state: &S, renderable: &mut impl RenderEntry<S>, path: PathBuf) {
 		fn ceil_to_multiple(value: u32, base: u32) -> u32 {
-			(value + (base - 1)) / base * base
+			value.div_ceil(base) * base
 		}
 
 		let render_state: &State = state.get();
@@ -17,8 +17,17 @@ for (directory, folders, files) in os.walk(SOURCE):
     for file in files:
         if not file.endswith(".laz"):
             continue
-        if all(map(lambda t: t not in file, TYPES)):
+        types = [t for t in TYPES if t in file]
+        if len(types) == 0:
             continue
         path = directory + "/" + file
         print(path)
-        subprocess.run(["treee", "importer", path, "-o=" + OUTPUT + file[:-4]])
+        subprocess.run(
+            [
+                "treee",
+                "importer",
+                path,
+                "-o=" + OUTPUT + file[:-4],
+                "--preset=" + types[0].lower(),
+            ]
+        )
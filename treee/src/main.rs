@@ -1,14 +1,15 @@
-use std::io::Write;
+use std::{io::Write, process::ExitCode};
 
 use clap::{CommandFactory, Parser};
 use colored::Colorize;
 
-fn main() {
+fn main() -> ExitCode {
 	if std::env::args().len() > 1 {
 		cli()
 	} else {
-		interactive()
-	};
+		interactive();
+		ExitCode::SUCCESS
+	}
 }
 
 fn interactive() {
@@ -24,7 +25,22 @@ fn interactive() {
 		std::io::stdin().read_line(&mut line).unwrap();
 		match InteractiveCommand::try_parse_from(line.split_whitespace()) {
 			Ok(InteractiveCommand::Importer(command)) => {
-				if let Err(err) = importer::run(command) {
+				if let Err(err) = importer::run(*command) {
+					println!("Error: {}", err);
+				}
+			},
+			Ok(InteractiveCommand::Batch(command)) => {
+				if let Err(err) = importer::batch(*command) {
+					println!("Error: {}", err);
+				}
+			},
+			Ok(InteractiveCommand::Inspect(command)) => {
+				if let Err(err) = importer::inspect(command) {
+					println!("Error: {}", err);
+				}
+			},
+			Ok(InteractiveCommand::Evaluate(command)) => {
+				if let Err(err) = importer::evaluate(command) {
 					println!("Error: {}", err);
 				}
 			},
@@ -50,16 +66,24 @@ fn interactive() {
 	}
 }
 
-fn cli() {
+/// Errors go to stderr, so the progress events are the only output on stdout.
+fn cli() -> ExitCode {
 	let res = match Command::parse() {
-		Command::Importer(command) => importer::run(command).map_err(Error::from),
+		Command::Importer(command) => importer::run(*command).map_err(Error::from),
+		Command::Batch(command) => importer::batch(*command).map_err(Error::from),
+		Command::Inspect(command) => importer::inspect(command).map_err(Error::from),
+		Command::Evaluate(command) => importer::evaluate(command).map_err(Error::from),
 		Command::Viewer => viewer::Runner::new()
 			.map_err(viewer::Error::RenderError)
 			.and_then(|mut runner| viewer::run(&mut runner))
 			.map_err(Error::Viewer),
 	};
-	if let Err(err) = res {
-		println!("Error: {}", err);
+	match res {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(err) => {
+			eprintln!("Error: {}", err);
+			ExitCode::FAILURE
+		},
 	}
 }
 
@@ -67,7 +91,13 @@ fn cli() {
 #[command(arg_required_else_help = false)]
 enum InteractiveCommand {
 	/// Start importer
-	Importer(importer::Command),
+	Importer(Box<importer::Command>),
+	/// Import every file in a folder into its own project
+	Batch(Box<importer::BatchCommand>),
+	/// Show information about an input file or project
+	Inspect(importer::InspectCommand),
+	/// Score the segments of a project against reference trees
+	Evaluate(importer::EvaluateCommand),
 	/// Start viewer
 	Viewer,
 	/// Quit application
@@ -77,7 +107,13 @@ enum InteractiveCommand {
 #[derive(clap::Parser)]
 enum Command {
 	/// Start importer
-	Importer(importer::Command),
+	Importer(Box<importer::Command>),
+	/// Import every file in a folder into its own project
+	Batch(Box<importer::BatchCommand>),
+	/// Show information about an input file or project
+	Inspect(importer::InspectCommand),
+	/// Score the segments of a project against reference trees
+	Evaluate(importer::EvaluateCommand),
 	/// Start viewer
 	Viewer,
 }
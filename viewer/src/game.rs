@@ -55,7 +55,7 @@ impl World {
 		let project = Project::empty();
 		let egui = render::egui::Context::default();
 
-		let (state, window) = render::State::new(&project.name, &runner, &egui).await?;
+		let (state, window) = render::State::new(&project.name, runner, &egui).await?;
 		let state = State::new(state);
 		let state = Arc::new(state);
 
@@ -70,7 +70,7 @@ impl World {
 			None,
 			project.properties[0].clone(),
 			&window,
-		);
+		)?;
 
 		Ok(Self {
 			egui,
@@ -95,14 +95,14 @@ trait GameExt {
 		path: Option<PathBuf>,
 		property: (String, String, u32),
 		window: &Window,
-	) -> Tree<ProjectScene>;
+	) -> Result<Tree<ProjectScene>, project::Error>;
 	fn change_project(&mut self);
 
 	fn check_reload(&mut self);
 
 	fn current_project_time(&self) -> std::time::SystemTime;
 
-	fn reload(&mut self, project_time: std::time::SystemTime);
+	fn reload(&mut self, project_time: std::time::SystemTime) -> Result<(), project::Error>;
 
 	fn raycast(&mut self);
 }
@@ -114,7 +114,7 @@ impl GameExt for Game<ProjectCustomState> {
 		path: Option<PathBuf>,
 		property: (String, String, u32),
 		window: &Window,
-	) -> Tree<ProjectScene> {
+	) -> Result<Tree<ProjectScene>, project::Error> {
 		let scene = ProjectScene {
 			root: Node::new(&project.root, &state),
 			segment: None,
@@ -125,10 +125,11 @@ impl GameExt for Game<ProjectCustomState> {
 					segments.push("segments");
 					Reader::new(segments, &property.0)
 				})
-				.unwrap_or(Reader::fake()),
-			loaded_manager: LoadedManager::new(state.clone(), path, &property.0),
+				.transpose()?
+				.unwrap_or_else(Reader::fake),
+			loaded_manager: LoadedManager::new(state.clone(), path, &property.0)?,
 		};
-		Tree::new(state, property, window, scene)
+		Ok(Tree::new(state, property, window, scene))
 	}
 	fn change_project(&mut self) {
 		let Some(path) = rfd::FileDialog::new()
@@ -137,8 +138,11 @@ impl GameExt for Game<ProjectCustomState> {
 		else {
 			return;
 		};
-		self.custom_state.path = Some(path);
-		self.reload(self.current_project_time());
+		let previous = self.custom_state.path.replace(path);
+		if let Err(err) = self.reload(self.current_project_time()) {
+			show_error("Failed to load project", &err);
+			self.custom_state.path = previous;
+		}
 	}
 
 	fn check_reload(&mut self) {
@@ -149,7 +153,9 @@ impl GameExt for Game<ProjectCustomState> {
 		if project_time.elapsed().unwrap() < std::time::Duration::from_millis(1000) {
 			return;
 		}
-		self.reload(project_time);
+		if let Err(err) = self.reload(project_time) {
+			show_error("Failed to reload project", &err);
+		}
 	}
 
 	fn current_project_time(&self) -> std::time::SystemTime {
@@ -161,21 +167,23 @@ impl GameExt for Game<ProjectCustomState> {
 			.unwrap_or(self.project_time)
 	}
 
-	fn reload(&mut self, project_time: std::time::SystemTime) {
+	fn reload(&mut self, project_time: std::time::SystemTime) -> Result<(), project::Error> {
 		self.custom_state.project_time = project_time;
 		let Some(path) = &self.custom_state.path else {
-			return;
+			return Ok(());
 		};
-		self.custom_state.project = Project::from_file(path);
+		let project = Project::from_file(path)?;
 		self.tree = Self::new_tree(
 			self.state.clone(),
-			&self.project,
+			&project,
 			Some(path.parent().unwrap().to_owned()),
-			self.project.properties[0].clone(),
+			project.properties[0].clone(),
 			&self.window,
-		);
+		)?;
+		self.custom_state.project = project;
 		self.window.set_title(&self.project.name);
 		self.window.request_redraw();
+		Ok(())
 	}
 
 	fn raycast(&mut self) {
@@ -196,18 +204,31 @@ impl GameExt for Game<ProjectCustomState> {
 			return;
 		};
 		let path = path.parent().unwrap().to_path_buf();
-		let mut reader = Reader::new(path, "segment");
-		if let Some(segment) = self.tree.scene.raycast(start, direction, &mut reader) {
-			self.tree.scene.segment = Some(Segment::new(
-				&self.state,
-				&mut self.tree.scene.segments,
-				segment,
-			));
-			self.window.request_redraw();
+		let result = Reader::new(path, "segment").and_then(|mut reader| {
+			let Some(segment) = self.tree.scene.raycast(start, direction, &mut reader)? else {
+				return Ok(None);
+			};
+			Segment::new(&self.state, &mut self.tree.scene.segments, segment).map(Some)
+		});
+		match result {
+			Ok(Some(segment)) => {
+				self.tree.scene.segment = Some(segment);
+				self.window.request_redraw();
+			},
+			Ok(None) => {},
+			Err(err) => show_error("Failed to load segment", &err),
 		}
 	}
 }
 
+fn show_error(title: &str, err: &project::Error) {
+	rfd::MessageDialog::new()
+		.set_level(rfd::MessageLevel::Error)
+		.set_title(title)
+		.set_description(err.to_string())
+		.show();
+}
+
 fn ui(ctx: &render::egui::Context, game: &mut Game<ProjectCustomState>) {
 	const HEIGHT: f32 = 10.0;
 	const LEFT: f32 = 100.0;
@@ -260,13 +281,19 @@ fn ui(ctx: &render::egui::Context, game: &mut Game<ProjectCustomState>) {
 									.scene
 									.loaded_manager
 									.change_property(&game.tree.context.property.0);
-								game.tree
+								let result = game
+									.tree
 									.scene
 									.segments
 									.change_property(&game.tree.context.property.0);
 								game.tree.context.update_lookup(&game.state);
-								if let Some(seg) = &mut game.tree.scene.segment {
-									seg.change_property(&game.state, &mut game.tree.scene.segments);
+								let result = result.and_then(|_| match &mut game.tree.scene.segment {
+									Some(seg) => seg.change_property(&game.state, &mut game.tree.scene.segments),
+									None => Ok(()),
+								});
+								if let Err(err) = result {
+									game.tree.scene.segment = None;
+									show_error("Failed to load property", &err);
 								}
 							}
 						});
@@ -361,6 +388,7 @@ fn ui(ctx: &render::egui::Context, game: &mut Game<ProjectCustomState>) {
 					.custom_state
 					.project
 					.segment(seg.index())
+					.unwrap_or_default()
 					.iter()
 					.enumerate()
 				{
@@ -376,7 +404,7 @@ fn ui(ctx: &render::egui::Context, game: &mut Game<ProjectCustomState>) {
 				ui.horizontal(|ui| {
 					ui.add_sized([LEFT, HEIGHT], Label::new("Points"));
 					if ui.add_sized([RIGHT, HEIGHT], Button::new("Save")).clicked() {
-						seg.save();
+						seg.save(&game.custom_state.project.georeference);
 					};
 				});
 			}
@@ -406,6 +434,7 @@ fn ui(ctx: &render::egui::Context, game: &mut Game<ProjectCustomState>) {
 							game.tree.context.environment.min,
 							game.tree.context.environment.max,
 							game.tree.context.environment.scale,
+							game.tree.context.environment.hidden,
 						);
 						game.window.request_redraw();
 					}
@@ -430,6 +459,7 @@ fn ui(ctx: &render::egui::Context, game: &mut Game<ProjectCustomState>) {
 							game.tree.context.environment.min,
 							game.tree.context.environment.max,
 							game.tree.context.environment.scale,
+							game.tree.context.environment.hidden,
 						);
 						game.window.request_redraw();
 					}
@@ -454,11 +484,36 @@ fn ui(ctx: &render::egui::Context, game: &mut Game<ProjectCustomState>) {
 							game.tree.context.environment.min,
 							game.tree.context.environment.max,
 							game.tree.context.environment.scale,
+							game.tree.context.environment.hidden,
 						);
 						game.window.request_redraw();
 					}
 				});
 
+				for (name, segment) in [
+					("Unassigned", project::UNASSIGNED_SEGMENT),
+					("Ground", project::GROUND_SEGMENT),
+				] {
+					let bit = 1 << (u32::MAX - segment);
+					let mut visible = game.tree.context.environment.hidden & bit == 0;
+					ui.horizontal(|ui| {
+						ui.add_sized([LEFT, HEIGHT], Label::new(name));
+						if ui
+							.add_sized([RIGHT, HEIGHT], Checkbox::new(&mut visible, "Visible"))
+							.changed()
+						{
+							game.tree.context.environment = render::PointCloudEnvironment::new(
+								&game.state,
+								game.tree.context.environment.min,
+								game.tree.context.environment.max,
+								game.tree.context.environment.scale,
+								game.tree.context.environment.hidden ^ bit,
+							);
+							game.window.request_redraw();
+						}
+					});
+				}
+
 				ui.horizontal(|ui| {
 					ui.add_sized([LEFT, HEIGHT], Label::new("Color Palette"));
 					ComboBox::from_id_source("color_palette")
@@ -13,6 +13,8 @@ pub enum Error {
 	NoFile,
 	#[error("{0}")]
 	RenderError(#[from] render::RenderError),
+	#[error(transparent)]
+	ProjectError(#[from] project::Error),
 }
 
 pub type Runner = render::Runner;
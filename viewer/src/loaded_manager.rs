@@ -37,7 +37,7 @@ enum WorkerUpdate {
 }
 
 impl LoadedManager {
-	pub fn new(state: Arc<State>, path: Option<PathBuf>, property: &str) -> Self {
+	pub fn new(state: Arc<State>, path: Option<PathBuf>, property: &str) -> Result<Self, project::Error> {
 		let (index_tx, index_rx) = crossbeam::channel::bounded(512);
 		let (pc_tx, pc_rx) = crossbeam::channel::bounded(512);
 
@@ -52,14 +52,16 @@ impl LoadedManager {
 			let mut reader = path
 				.clone()
 				.map(|path| Reader::new(path, property))
-				.unwrap_or(Reader::fake());
+				.transpose()?
+				.unwrap_or_else(Reader::fake);
 			let state = state.clone();
 
 			std::thread::spawn(move || loop {
 				for update in update_reciever.try_iter() {
 					match update {
 						WorkerUpdate::ChangeProperty(property, index) => {
-							reader.change_property(&property);
+							// a missing property file fails the property requests
+							let _ = reader.change_property(&property);
 							property_index = index;
 						},
 					}
@@ -92,7 +94,7 @@ impl LoadedManager {
 			});
 		}
 
-		Self {
+		Ok(Self {
 			available: HashMap::new(),
 			requested: HashSet::new(),
 			sender: index_tx,
@@ -103,7 +105,7 @@ impl LoadedManager {
 			property_default: render::PointCloudProperty::new_empty(&state),
 			property_available: HashMap::new(),
 			property_requested: HashSet::new(),
-		}
+		})
 	}
 
 	pub fn change_property(&mut self, name: &str) {
@@ -191,15 +193,19 @@ impl LoadedManager {
 }
 
 fn load_pointcloud(state: &State, reader: &mut Reader, index: usize) -> Option<render::PointCloud> {
-	let data = reader.get_points(index);
+	let data = reader.get_points(index).ok()?;
 	if data.is_empty() {
 		return None;
 	}
-	Some(render::PointCloud::new(state, &data))
+	let segments = reader.get_segments(index).ok()?;
+	if segments.len() != data.len() {
+		return None;
+	}
+	Some(render::PointCloud::with_segments(state, &data, &segments))
 }
 
 fn load_property(state: &State, reader: &mut Reader, index: usize) -> Option<render::PointCloudProperty> {
-	let data = reader.get_property(index);
+	let data = reader.get_property(index).ok()?;
 	if data.is_empty() {
 		return None;
 	}
@@ -4,37 +4,53 @@ use project::DataFile;
 
 pub struct Reader {
 	points: DataFile<project::Point>,
+	segment: DataFile<u32>,
 	property: DataFile<u32>,
 	path: PathBuf,
 }
 
 impl Reader {
-	pub fn new(mut path: PathBuf, property: &str) -> Self {
+	pub fn new(mut path: PathBuf, property: &str) -> Result<Self, project::Error> {
 		path.push("points.data");
-		let points = DataFile::open(&path);
+		let points = DataFile::open(&path)?;
+		path.set_file_name("segment.data");
+		let segment = DataFile::open(&path)?;
 		path.set_file_name(format!("{}.data", property));
-		let property = DataFile::open(&path);
-		Self { points, property, path }
+		let property = DataFile::open(&path)?;
+		Ok(Self { points, segment, property, path })
 	}
 
 	pub fn fake() -> Self {
 		Self {
 			points: DataFile::fake(),
+			segment: DataFile::fake(),
 			property: DataFile::fake(),
 			path: PathBuf::new(),
 		}
 	}
 
-	pub fn change_property(&mut self, property: &str) {
+	/// Without the new property file all property reads fail.
+	pub fn change_property(&mut self, property: &str) -> Result<(), project::Error> {
 		self.path.set_file_name(format!("{}.data", property));
-		self.property = DataFile::open(&self.path);
+		match DataFile::open(&self.path) {
+			Ok(file) => self.property = file,
+			Err(err) => {
+				self.property = DataFile::fake();
+				return Err(err);
+			},
+		}
+		Ok(())
 	}
 
-	pub fn get_points(&mut self, index: usize) -> Vec<project::Point> {
+	pub fn get_points(&mut self, index: usize) -> Result<Vec<project::Point>, project::Error> {
 		self.points.read(index)
 	}
 
-	pub fn get_property(&mut self, index: usize) -> Vec<u32> {
+	pub fn get_segments(&mut self, index: usize) -> Result<Vec<u32>, project::Error> {
+		self.segment.read(index)
+	}
+
+	pub fn get_property(&mut self, index: usize) -> Result<Vec<u32>, project::Error> {
 		self.property.read(index)
 	}
 }
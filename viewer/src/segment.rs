@@ -38,12 +38,12 @@ pub struct Segment {
 }
 
 impl Segment {
-	pub fn new(state: &State, reader: &mut Reader, index: NonZeroU32) -> Self {
-		let points = reader.get_points(index.get() as usize - 1);
+	pub fn new(state: &State, reader: &mut Reader, index: NonZeroU32) -> Result<Self, project::Error> {
+		let points = reader.get_points(index.get() as usize - 1)?;
 		let point_cloud = render::PointCloud::new(state, &points);
 
-		Self {
-			property: Self::load_property(state, reader, index.get() as usize - 1),
+		Ok(Self {
+			property: Self::load_property(state, reader, index.get() as usize - 1)?,
 			point_cloud,
 			mesh: MeshState::None,
 			render: MeshRender::Points,
@@ -51,16 +51,21 @@ impl Segment {
 			points,
 			alpha: 0.5,
 			sub_sample_distance: 0.1,
-		}
+		})
 	}
 
-	pub fn change_property(&mut self, state: &State, reader: &mut Reader) {
-		self.property = Self::load_property(state, reader, self.index.get() as usize - 1);
+	pub fn change_property(&mut self, state: &State, reader: &mut Reader) -> Result<(), project::Error> {
+		self.property = Self::load_property(state, reader, self.index.get() as usize - 1)?;
+		Ok(())
 	}
 
-	fn load_property(state: &State, reader: &mut Reader, index: usize) -> render::PointCloudProperty {
-		let data = reader.get_property(index);
-		render::PointCloudProperty::new(state, &data)
+	fn load_property(
+		state: &State,
+		reader: &mut Reader,
+		index: usize,
+	) -> Result<render::PointCloudProperty, project::Error> {
+		let data = reader.get_property(index)?;
+		Ok(render::PointCloudProperty::new(state, &data))
 	}
 
 	pub fn index(&self) -> NonZeroU32 {
@@ -114,7 +119,7 @@ impl Segment {
 		self.mesh = MeshState::Progress(Vec::new(), render::Mesh::new(state, &[]), reciever);
 	}
 
-	pub fn save(&self) {
+	pub fn save(&self, georeference: &project::Georeference) {
 		let Some(location) = rfd::FileDialog::new()
 			.add_filter("File", &["ply"])
 			.save_file()
@@ -129,10 +134,17 @@ impl Segment {
 			max = max.max(point.position);
 		}
 		let diff = (max + min) / 2.0;
+		let origin = georeference.to_source(Vector::new([diff[X], min[Y], diff[Z]]));
 
 		let mut file = BufWriter::new(std::fs::File::create(location).unwrap());
 		file.write_all(b"ply\n").unwrap();
 		file.write_all(b"format ascii 1.0\n").unwrap();
+		file.write_all(format!("comment origin {} {} {}\n", origin[X], origin[Y], origin[Z]).as_bytes())
+			.unwrap();
+		if let Some(crs) = &georeference.crs {
+			file.write_all(format!("comment crs {}\n", crs.replace('\n', " ")).as_bytes())
+				.unwrap();
+		}
 		file.write_all(format!("element vertex {}\n", self.points.len()).as_bytes())
 			.unwrap();
 		file.write_all(b"property float x\n").unwrap();
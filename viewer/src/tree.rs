@@ -56,7 +56,9 @@ impl Node {
 
 				Data::Branch { children: Box::new(children), segments }
 			},
-			IndexData::Leaf { segments } => Data::Leaf { segments: segments.clone() },
+			IndexData::Leaf { segments } => Data::Leaf {
+				segments: segments.iter().copied().collect(),
+			},
 		};
 
 		let points = [
@@ -235,7 +237,12 @@ impl Node {
 		(t_max >= t_min).then_some(t_min)
 	}
 
-	pub fn raycast(&self, start: Vector<3, f32>, direction: Vector<3, f32>, reader: &mut Reader) -> Option<NonZeroU32> {
+	pub fn raycast(
+		&self,
+		start: Vector<3, f32>,
+		direction: Vector<3, f32>,
+		reader: &mut Reader,
+	) -> Result<Option<NonZeroU32>, project::Error> {
 		match &self.data {
 			Data::Branch { children, segments: _ } => {
 				let mut order = Vec::new();
@@ -248,17 +255,24 @@ impl Node {
 
 				order.sort_unstable_by(|(_, dist_a), (_, dist_b)| dist_a.total_cmp(dist_b));
 
-				order
-					.into_iter()
-					.find_map(|(child, _)| child.raycast(start, direction, reader))
+				for (child, _) in order {
+					if let Some(segment) = child.raycast(start, direction, reader)? {
+						return Ok(Some(segment));
+					}
+				}
+				Ok(None)
 			},
 			Data::Leaf { .. } => {
 				let mut best = None;
 				let mut best_dist = f32::MAX;
-				let data = reader.get_points(self.index);
-				let segments = reader.get_property(self.index);
+				let data = reader.get_points(self.index)?;
+				let segments = reader.get_property(self.index)?;
 
 				for (point, segment) in data.into_iter().zip(segments) {
+					// reserved segments have no segment to open
+					if project::is_reserved_segment(segment) {
+						continue;
+					}
 					let diff = point.position - start;
 					let diff_length = diff.length();
 					if diff_length >= best_dist {
@@ -275,7 +289,7 @@ impl Node {
 						}
 					}
 				}
-				best
+				Ok(best)
 			},
 		}
 	}
@@ -287,8 +301,10 @@ impl ProjectScene {
 		start: Vector<3, f32>,
 		direction: Vector<3, f32>,
 		reader: &mut Reader,
-	) -> Option<NonZeroU32> {
-		self.root.raycast_distance(start, direction)?;
+	) -> Result<Option<NonZeroU32>, project::Error> {
+		if self.root.raycast_distance(start, direction).is_none() {
+			return Ok(None);
+		}
 		self.root.raycast(start, direction, reader)
 	}
 }
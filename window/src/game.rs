@@ -97,6 +97,12 @@ pub struct Time {
 	last: std::time::Instant,
 }
 
+impl Default for Time {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Time {
 	pub fn new() -> Self {
 		Self { last: std::time::Instant::now() }
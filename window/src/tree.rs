@@ -65,8 +65,8 @@ impl<T> Tree<T> {
 				background: DEFAULT_BACKGROUND,
 				camera: Camera::new(&state, window.get_aspect()),
 				lookup_name,
-				lookup: render::Lookup::new_png(&state, lookup_name.data(), property.2),
-				environment: render::PointCloudEnvironment::new(&state, u32::MIN, u32::MAX, 1.0),
+				lookup: lookup(&state, lookup_name, &property),
+				environment: render::PointCloudEnvironment::new(&state, u32::MIN, u32::MAX, 1.0, 0),
 				eye_dome: render::EyeDome::new(&state, window.config(), window.depth_texture(), 0.7),
 				eye_dome_active: true,
 				voxels_active: false,
@@ -95,6 +95,14 @@ pub struct TreeContext {
 
 impl TreeContext {
 	pub fn update_lookup(&mut self, state: &State) {
-		self.lookup = render::Lookup::new_png(state, self.lookup_name.data(), self.property.2);
+		self.lookup = lookup(state, self.lookup_name, &self.property);
+	}
+}
+
+fn lookup(state: &State, lookup_name: LookupName, property: &(String, String, u32)) -> render::Lookup {
+	if property.0 == project::COLOR_PROPERTY {
+		render::Lookup::new_color(state, lookup_name.data())
+	} else {
+		render::Lookup::new_png(state, lookup_name.data(), property.2)
 	}
 }